@@ -0,0 +1,91 @@
+use std::fmt;
+use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
+
+use crate::{DNSDistConsoleError, Phase};
+
+/// The family of the addresses a name can be resolved to that connections are attempted to, see
+/// `DNSDistConsoleBuilder::address_family`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressFamily {
+    /// Only IPv4 addresses
+    V4,
+    /// Only IPv6 addresses
+    V6,
+    /// Addresses of both families, in the order returned by the resolver
+    Any,
+}
+
+impl AddressFamily {
+    /// Returns whether `addr` belongs to this family
+    pub(crate) fn matches(self, addr: &SocketAddr) -> bool {
+        match self {
+            AddressFamily::V4 => addr.is_ipv4(),
+            AddressFamily::V6 => addr.is_ipv6(),
+            AddressFamily::Any => true,
+        }
+    }
+}
+
+impl fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressFamily::V4 => write!(f, "IPv4"),
+            AddressFamily::V6 => write!(f, "IPv6"),
+            AddressFamily::Any => write!(f, "IPv4 or IPv6"),
+        }
+    }
+}
+
+/// Parses a scoped IPv6 address like `fe80::1%eth0` or `fe80::1%2`, returning `None` if `host`
+/// is not a scoped IPv6 address
+pub(crate) fn parse_scoped_ipv6(
+    host: &str,
+    port: u16,
+) -> Result<Option<SocketAddr>, DNSDistConsoleError> {
+    let (addr, zone) = match host.find('%') {
+        Some(pos) => (&host[..pos], &host[pos + 1..]),
+        None => return Ok(None),
+    };
+    let addr = match addr.parse::<Ipv6Addr>() {
+        Ok(addr) => addr,
+        Err(_) => return Ok(None),
+    };
+
+    let scope_id = match zone.parse::<u32>() {
+        Ok(scope_id) => scope_id,
+        Err(_) => match interface_index(zone) {
+            Some(scope_id) => scope_id,
+            None => {
+                return Err(DNSDistConsoleError::TransportError(
+                    Phase::Connect,
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("unknown network interface {}", zone),
+                    ),
+                ))
+            }
+        },
+    };
+
+    Ok(Some(SocketAddr::V6(SocketAddrV6::new(
+        addr, port, 0, scope_id,
+    ))))
+}
+
+/// Returns the index of the network interface named `name`, if any
+#[cfg(unix)]
+fn interface_index(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // if_nametoindex only reads the NUL-terminated string we pass
+    let idx = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if idx == 0 {
+        None
+    } else {
+        Some(idx)
+    }
+}
+
+#[cfg(not(unix))]
+fn interface_index(_name: &str) -> Option<u32> {
+    None
+}
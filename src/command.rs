@@ -0,0 +1,75 @@
+/// The prefixes of the functions accepted in read-only mode
+pub const READ_ONLY_COMMAND_PREFIXES: &[&str] = &["show", "dump", "get"];
+
+/// Returns whether `command` is a single call to a function whose name starts with one of
+/// `READ_ONLY_COMMAND_PREFIXES`, with only literal arguments
+///
+/// Since commands are Lua code, anything that could call another function is rejected: method
+/// calls like `getServer(0):setDown()`, several statements, and nested calls in the arguments,
+/// including Lua's `f"string"` and `f{table}` call syntax.
+pub(crate) fn is_read_only(command: &str) -> bool {
+    let command = command.trim();
+    let name_len = command
+        .find(|c: char| !is_identifier(c))
+        .unwrap_or(command.len());
+    let name = &command[..name_len];
+    if !READ_ONLY_COMMAND_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+    {
+        return false;
+    }
+
+    let call = command[name_len..].trim_start();
+    if call.len() < 2 || !call.starts_with('(') || !call.ends_with(')') {
+        return false;
+    }
+    literal_arguments(&call[1..call.len() - 1])
+}
+
+/// Returns whether `response` looks like the report of a Lua error, see
+/// `DNSDistConsole::execute_checked`
+pub(crate) fn is_error_response(response: &str) -> bool {
+    response.starts_with("Error: ") || response.contains("stack traceback:")
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Returns whether `arguments` only holds literals: numbers, strings, booleans, names and
+/// tables of those
+fn literal_arguments(arguments: &str) -> bool {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut after_identifier = false;
+    for c in arguments.chars() {
+        if let Some(delimiter) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == delimiter {
+                quote = None;
+            } else if c == '\n' {
+                return false;
+            }
+            continue;
+        }
+
+        match c {
+            // a string or a table right after a name is a function call in Lua
+            '"' | '\'' | '{' if after_identifier => return false,
+            '"' | '\'' => quote = Some(c),
+            c if is_identifier(c) => {
+                after_identifier = true;
+                continue;
+            }
+            c if c == ' ' || c == '\t' => continue,
+            '{' | '}' | ',' | '.' | '-' | '+' | '=' => {}
+            _ => return false,
+        }
+        after_identifier = false;
+    }
+    quote.is_none()
+}
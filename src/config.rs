@@ -0,0 +1,240 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{ConsoleKey, DNSDistConsoleError};
+
+/// Where the key of a console comes from
+#[derive(Clone)]
+pub enum ConfigKey {
+    /// The key, given in the configuration file as its base64 encoding, like generated by
+    /// dnsdist's `makeKey()`, and decoded when the file is parsed so that the encoded form is
+    /// not kept around
+    Base64(ConsoleKey),
+    /// The path of a file holding the base64 encoding of the key
+    File(PathBuf),
+}
+
+/// The settings needed to connect to a console, usually loaded from a configuration file
+/// describing several named targets
+///
+/// The configuration file uses a subset of TOML: one table per target, holding the `host`, the
+/// `port`, and either the base64-encoded `key` or the path of a `key_file` holding it.
+///
+/// ```toml
+/// # comments and blank lines are ignored
+/// [edge1]
+/// host = "192.0.2.1"
+/// port = 5199
+/// key = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE="
+///
+/// [edge2]
+/// host = "edge2.example.net"
+/// port = 5199
+/// key_file = "/etc/dnsdist/console.key"
+/// ```
+// Do not derive Debug: it would print the key.
+#[derive(Clone)]
+pub struct ConsoleConfig {
+    /// The network address or the name of the DNSDist server
+    pub host: String,
+    /// The port of the console on the remote server
+    pub port: u16,
+    /// Where the pre-shared key comes from
+    pub key: ConfigKey,
+}
+
+impl ConsoleConfig {
+    /// Reads the configuration file at `path` and returns its targets, by name
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the configuration file
+    pub fn load_targets(
+        path: impl AsRef<Path>,
+    ) -> Result<BTreeMap<String, ConsoleConfig>, DNSDistConsoleError> {
+        let content = fs::read_to_string(path)?;
+        let targets = ConsoleConfig::parse_targets(&content);
+        // the file might hold keys
+        let mut bytes = content.into_bytes();
+        sodiumoxide::utils::memzero(&mut bytes);
+        targets
+    }
+
+    /// Parses the content of a configuration file and returns its targets, by name
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - The content of the configuration file
+    pub fn parse_targets(
+        content: &str,
+    ) -> Result<BTreeMap<String, ConsoleConfig>, DNSDistConsoleError> {
+        let mut targets = BTreeMap::new();
+        let mut current: Option<(String, PartialConfig)> = None;
+
+        for (idx, line) in content.lines().enumerate() {
+            let line_number = idx + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                let name = match strip_comment(line) {
+                    Some(section) if section.ends_with(']') => section[1..section.len() - 1].trim(),
+                    _ => return Err(config_error(line_number, "invalid table header")),
+                };
+                if name.is_empty() {
+                    return Err(config_error(line_number, "empty target name"));
+                }
+                if let Some((name, partial)) = current.take() {
+                    targets.insert(name.clone(), partial.finish(&name)?);
+                }
+                if targets.contains_key(name) {
+                    return Err(config_error(line_number, "duplicate target"));
+                }
+                current = Some((name.to_string(), PartialConfig::default()));
+                continue;
+            }
+
+            let (name, value) = match line.find('=') {
+                Some(pos) => (line[..pos].trim(), line[pos + 1..].trim()),
+                None => return Err(config_error(line_number, "expected `name = value`")),
+            };
+            let partial = match current.as_mut() {
+                Some((_, partial)) => partial,
+                None => return Err(config_error(line_number, "setting outside of a target")),
+            };
+            match name {
+                "host" => partial.host = Some(parse_string(value, line_number)?),
+                "port" => partial.port = Some(parse_port(value, line_number)?),
+                "key" => partial.key = Some(ConfigKey::Base64(parse_key(value, line_number)?)),
+                "key_file" => {
+                    partial.key = Some(ConfigKey::File(PathBuf::from(parse_string(
+                        value,
+                        line_number,
+                    )?)))
+                }
+                _ => return Err(config_error(line_number, "unknown setting")),
+            }
+        }
+
+        if let Some((name, partial)) = current.take() {
+            targets.insert(name.clone(), partial.finish(&name)?);
+        }
+        Ok(targets)
+    }
+
+    /// Returns the key of the console, reading it from its file if needed
+    pub fn console_key(&self) -> Result<ConsoleKey, DNSDistConsoleError> {
+        match &self.key {
+            ConfigKey::Base64(key) => Ok(key.clone()),
+            ConfigKey::File(path) => {
+                let key_b64 = fs::read_to_string(path)?;
+                let key = key_b64.trim().parse();
+                let mut bytes = key_b64.into_bytes();
+                sodiumoxide::utils::memzero(&mut bytes);
+                key
+            }
+        }
+    }
+}
+
+/// A target whose settings have not all been seen yet
+#[derive(Default)]
+struct PartialConfig {
+    host: Option<String>,
+    port: Option<u16>,
+    key: Option<ConfigKey>,
+}
+
+impl PartialConfig {
+    fn finish(self, name: &str) -> Result<ConsoleConfig, DNSDistConsoleError> {
+        let missing = |setting: &str| {
+            DNSDistConsoleError::ConfigError(format!("target `{}` has no {}", name, setting))
+        };
+        Ok(ConsoleConfig {
+            host: self.host.ok_or_else(|| missing("host"))?,
+            port: self.port.ok_or_else(|| missing("port"))?,
+            key: self.key.ok_or_else(|| missing("key or key_file"))?,
+        })
+    }
+}
+
+fn config_error(line_number: usize, message: &str) -> DNSDistConsoleError {
+    DNSDistConsoleError::ConfigError(format!("line {}: {}", line_number, message))
+}
+
+/// Returns `line` without its trailing comment, if any, or `None` if a string is not terminated
+fn strip_comment(line: &str) -> Option<&str> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (pos, c) in line.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(delimiter) if c == delimiter => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' => return Some(line[..pos].trim_end()),
+            None => {}
+        }
+    }
+    match quote {
+        Some(_) => None,
+        None => Some(line),
+    }
+}
+
+/// Parses a TOML basic string, with escapes, or a literal string, without
+fn parse_string(value: &str, line_number: usize) -> Result<String, DNSDistConsoleError> {
+    let value = match strip_comment(value) {
+        Some(value) if value.len() >= 2 => value,
+        _ => return Err(config_error(line_number, "expected a string")),
+    };
+    if value.starts_with('\'') && value.ends_with('\'') {
+        return Ok(value[1..value.len() - 1].to_string());
+    }
+    if !value.starts_with('"') || !value.ends_with('"') {
+        return Err(config_error(line_number, "expected a string"));
+    }
+
+    let mut parsed = String::with_capacity(value.len());
+    let mut chars = value[1..value.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            return Err(config_error(line_number, "unescaped quote in a string"));
+        }
+        if c != '\\' {
+            parsed.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => parsed.push('"'),
+            Some('\\') => parsed.push('\\'),
+            Some('n') => parsed.push('\n'),
+            Some('t') => parsed.push('\t'),
+            _ => return Err(config_error(line_number, "unsupported escape sequence")),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Parses a string holding a base64-encoded key, wiping the intermediate copy
+fn parse_key(value: &str, line_number: usize) -> Result<ConsoleKey, DNSDistConsoleError> {
+    let key_b64 = parse_string(value, line_number)?;
+    let key = key_b64.parse::<ConsoleKey>();
+    let mut bytes = key_b64.into_bytes();
+    sodiumoxide::utils::memzero(&mut bytes);
+    match key {
+        Ok(key) => Ok(key),
+        Err(e) => Err(config_error(line_number, &e.to_string())),
+    }
+}
+
+fn parse_port(value: &str, line_number: usize) -> Result<u16, DNSDistConsoleError> {
+    match strip_comment(value).map(|value| value.replace('_', "").parse::<u16>()) {
+        Some(Ok(port)) => Ok(port),
+        _ => Err(config_error(line_number, "expected a port number")),
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::DNSDistConsoleError;
+
+/// The pre-shared key used to encrypt exchanges with a dnsdist console
+///
+/// The key is never printed by its `Debug` implementation, comparisons are done in constant
+/// time, and its bytes are overwritten with zeros when it is dropped. Arrays converted into a
+/// `ConsoleKey` are copied, wiping the original is up to the caller.
+#[derive(Clone)]
+pub struct ConsoleKey([u8; sodiumoxide::crypto::secretbox::KEYBYTES]);
+
+impl ConsoleKey {
+    /// Returns the raw bytes of the key
+    pub fn as_bytes(&self) -> &[u8; sodiumoxide::crypto::secretbox::KEYBYTES] {
+        &self.0
+    }
+
+    /// Rejects keys that cannot be right, like an all-zero key which usually comes from a
+    /// buffer that was never filled and would only cause a confusing failure later
+    pub(crate) fn validate(&self) -> Result<(), DNSDistConsoleError> {
+        if is_zero(&self.0) {
+            return Err(DNSDistConsoleError::InvalidKey(
+                "the key is all zeros".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn is_zero(key: &[u8]) -> bool {
+    key.iter().fold(0, |acc, byte| acc | byte) == 0
+}
+
+impl From<[u8; sodiumoxide::crypto::secretbox::KEYBYTES]> for ConsoleKey {
+    fn from(key: [u8; sodiumoxide::crypto::secretbox::KEYBYTES]) -> Self {
+        ConsoleKey(key)
+    }
+}
+
+impl FromStr for ConsoleKey {
+    type Err = DNSDistConsoleError;
+
+    /// Parses a base64-encoded console key, as generated by dnsdist's `makeKey()`, see
+    /// `decode_key` for the accepted encodings
+    fn from_str(key_b64: &str) -> Result<Self, Self::Err> {
+        let mut key = ConsoleKey([0; sodiumoxide::crypto::secretbox::KEYBYTES]);
+        decode_key_into(key_b64, &mut key.0)?;
+        Ok(key)
+    }
+}
+
+impl PartialEq for ConsoleKey {
+    fn eq(&self, other: &Self) -> bool {
+        sodiumoxide::utils::memcmp(&self.0, &other.0)
+    }
+}
+
+impl Eq for ConsoleKey {}
+
+impl Drop for ConsoleKey {
+    fn drop(&mut self) {
+        sodiumoxide::utils::memzero(&mut self.0);
+    }
+}
+
+impl fmt::Debug for ConsoleKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ConsoleKey(***)")
+    }
+}
+
+/// Decodes a base64-encoded console key, as generated by dnsdist's `makeKey()`
+///
+/// dnsdist uses the standard base64 alphabet, but keys copied from other tools are sometimes
+/// encoded with the url-safe one, using `-` and `_` instead of `+` and `/`: both are accepted,
+/// with or without the trailing padding.
+///
+/// # Arguments
+///
+/// * `key_b64` - The base64 encoding of a key of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+pub fn decode_key(
+    key_b64: &str,
+) -> Result<[u8; sodiumoxide::crypto::secretbox::KEYBYTES], DNSDistConsoleError> {
+    let key: ConsoleKey = key_b64.parse()?;
+    Ok(*key.as_bytes())
+}
+
+/// Decodes a base64-encoded console key into `key`, wiping the intermediate buffer
+fn decode_key_into(
+    key_b64: &str,
+    key: &mut [u8; sodiumoxide::crypto::secretbox::KEYBYTES],
+) -> Result<(), DNSDistConsoleError> {
+    // the two alphabets only differ by two characters, which cannot be mixed in a valid key
+    let config = if key_b64.contains(&['-', '_'][..]) {
+        base64::URL_SAFE_NO_PAD
+    } else {
+        base64::STANDARD_NO_PAD
+    };
+    let mut decoded = match base64::decode_config(key_b64.trim_end_matches('='), config) {
+        Ok(decoded) => decoded,
+        Err(e) => return Err(DNSDistConsoleError::InvalidKey(e.to_string())),
+    };
+    let result = if decoded.len() == key.len() {
+        key.copy_from_slice(&decoded);
+        Ok(())
+    } else {
+        Err(DNSDistConsoleError::InvalidKey(format!(
+            "expected {} bytes, got {}",
+            sodiumoxide::crypto::secretbox::KEYBYTES,
+            decoded.len()
+        )))
+    };
+    sodiumoxide::utils::memzero(&mut decoded);
+    result?;
+
+    if is_zero(key) {
+        return Err(DNSDistConsoleError::InvalidKey(
+            "the key is all zeros".to_string(),
+        ));
+    }
+    Ok(())
+}
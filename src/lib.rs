@@ -1,30 +1,227 @@
 use std::convert::TryInto;
-use std::io::{Read, Write};
-use std::net::{SocketAddr, TcpStream};
-use std::time::Duration;
+use std::fmt;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use thiserror::Error;
 
+mod address;
+mod command;
+mod config;
+mod key;
+#[cfg(feature = "test-util")]
+mod mock;
+mod parsers;
+mod pool;
+mod protocol;
+mod proxy;
+mod query;
+mod sockopt;
+mod transport;
+
+pub use address::AddressFamily;
+pub use command::READ_ONLY_COMMAND_PREFIXES;
+pub use config::{ConfigKey, ConsoleConfig};
+pub use key::{decode_key, ConsoleKey};
+#[cfg(feature = "test-util")]
+pub use mock::MockConsoleServer;
+pub use parsers::{
+    parse_acl, parse_dump_stats, parse_security_status, parse_show_frontends, parse_show_pools,
+    parse_show_servers, parse_statistics, parse_version, Frontend, Netmask, ParseError, PoolInfo,
+    SecurityStatus, ServerEntry, ServerVersion, Statistics,
+};
+pub use pool::{ConsolePool, PooledConsole};
+pub use protocol::{
+    decode_frame_length, derive_session_nonces, encode_frame_length, ConsoleProtocol,
+    FRAME_LENGTH_SIZE,
+};
+pub use proxy::ProxyHeader;
+pub use query::{PoolServersQuery, Query, ServerStatus, ServerStatusQuery};
+use transport::Transport;
+
 /// DNSDistConsoleError enumerates all possible errors returned by this library.
 #[derive(Error, Debug)]
 pub enum DNSDistConsoleError {
     #[error(transparent)]
     AddrParseError(#[from] std::net::AddrParseError),
 
-    #[error("connection eror: `{0}`")]
-    TransportError(String),
+    #[error("transport error while {0}: {1}")]
+    TransportError(Phase, #[source] std::io::Error),
+
+    #[error("missing parameter: `{0}`")]
+    MissingParameter(String),
+
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("invalid configuration: {0}")]
+    ConfigError(String),
+
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+
+    #[error("handshake failed: {0}")]
+    HandshakeFailed(String),
+
+    #[error("command too large: {0} bytes")]
+    CommandTooLarge(usize),
+
+    #[error("response too large: {0} bytes")]
+    ResponseTooLarge(usize),
+
+    #[error("malformed frame: {0} bytes is too short to hold an encrypted response, check that the server is a dnsdist console and that the key matches the one set with setKey()")]
+    MalformedFrame(usize),
+
+    #[error("the server closed the connection, reconnect")]
+    ConnectionClosed,
+
+    #[error("the connection is in an inconsistent state after a previous error, reconnect")]
+    ConnectionPoisoned,
+
+    #[error("unable to decrypt the response")]
+    DecryptionError,
+
+    #[error("unable to decrypt the first response from the server, check that the console key matches the one set with setKey() in dnsdist")]
+    KeyMismatch,
+
+    /// dnsdist closes the connection without replying when it cannot decrypt a command, so this
+    /// almost always means that the key does not match the one of the server
+    #[error("the server closed the connection before sending any response, check that the console key matches the one set with setKey() in dnsdist")]
+    ClosedBeforeKeyConfirmed,
+
+    #[error("the operation did not complete in time")]
+    Timeout,
+
+    #[error("the command failed: {}", .0.trim_end())]
+    CommandError(String),
+
+    #[error("command rejected in read-only mode: `{0}`")]
+    CommandRejected(String),
+
+    #[error("command {0} of the batch failed: {1}")]
+    BatchCommandFailed(usize, #[source] Box<DNSDistConsoleError>),
+
+    #[error("unable to parse the response: {0}")]
+    ParseError(#[from] ParseError),
+
+    #[error(transparent)]
+    Utf8Error(#[from] std::string::FromUtf8Error),
 
     /// Represents all other cases of `std::io::Error`.
     #[error(transparent)]
     IOError(#[from] std::io::Error),
 }
 
+/// The step of the exchange with the console during which a transport error occurred
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Establishing the connection, including resolving the name of the server
+    Connect,
+    /// Exchanging nonces with the server
+    Handshake,
+    /// Sending a command
+    Send,
+    /// Receiving a response
+    Receive,
+}
+
+/// The direction of the bytes passed to a wire tap, see `DNSDistConsole::set_wire_tap`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent to the server
+    Sent,
+    /// Received from the server
+    Received,
+}
+
+/// A callback observing the frames exchanged with the server, see `DNSDistConsole::set_wire_tap`
+pub type WireTap = Box<dyn FnMut(Direction, &[u8]) + Send>;
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Connect => write!(f, "connecting"),
+            Phase::Handshake => write!(f, "performing the handshake"),
+            Phase::Send => write!(f, "sending a command"),
+            Phase::Receive => write!(f, "receiving a response"),
+        }
+    }
+}
+
+impl DNSDistConsoleError {
+    /// Wraps an I/O error that occurred during `phase`, reporting the expiration of a socket
+    /// timeout as `ErrorKind::TimedOut` on every platform
+    fn from_transport(phase: Phase, err: std::io::Error) -> DNSDistConsoleError {
+        match err.kind() {
+            ErrorKind::WouldBlock => DNSDistConsoleError::TransportError(
+                phase,
+                std::io::Error::new(ErrorKind::TimedOut, "timed out"),
+            ),
+            _ => DNSDistConsoleError::TransportError(phase, err),
+        }
+    }
+}
+
+/// The timeout used when connecting to the console, unless a different one has been set
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The time `execute_before` waits before its first retry
+pub const DEADLINE_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The TCP keepalive interval used on console connections, unless a different one has been set
+pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The maximum size of a response, in bytes, unless a different one has been set
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 64 * 1024 * 1024;
+
+/// The maximum size of the commands sent by `DNSDistConsole::pipeline` before reading their
+/// responses, in bytes
+const PIPELINE_WINDOW: usize = 64 * 1024;
+
+/// The size of the chunks a response is written in by `DNSDistConsole::receive_to_writer`
+const WRITER_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The maximum size of a command, in bytes: the encrypted command, which is
+/// `sodiumoxide::crypto::secretbox::MACBYTES` larger, has to fit in a 32-bit length
+pub const MAX_COMMAND_SIZE: usize = u32::MAX as usize - sodiumoxide::crypto::secretbox::MACBYTES;
+
 /// An encrypted connection to a dnsdist console
+///
+/// A connection is `Send`, so it can be handed over to another thread. Sending a command and
+/// receiving a response require a mutable reference, so a connection can only be used by one
+/// thread at a time: use a `ConsolePool` to share connections between threads.
+///
+/// The `Debug` implementation only shows the peer and the state of the connection, never the
+/// key or the nonces.
+// Do not derive Debug: it would print the secret key and the nonces.
 pub struct DNSDistConsole {
-    stream: TcpStream,
-    writing_nonce: sodiumoxide::crypto::secretbox::Nonce,
-    reading_nonce: sodiumoxide::crypto::secretbox::Nonce,
-    secret_key: sodiumoxide::crypto::secretbox::xsalsa20poly1305::Key,
+    /// only ever `None` once the stream has been taken by `into_stream`
+    stream: Option<Transport>,
+    protocol: ConsoleProtocol,
+    max_response_size: usize,
+    max_command_size: usize,
+    poisoned: bool,
+    read_only: bool,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    bytes_sent: u64,
+    bytes_received: u64,
+    /// how the connection was established, to be able to reconnect
+    origin: Option<Origin>,
+    wire_tap: Option<WireTap>,
+}
+
+/// How a connection was established
+enum Origin {
+    Tcp(Box<DNSDistConsoleBuilder>),
+    #[cfg(unix)]
+    Unix(PathBuf),
 }
 
 impl DNSDistConsole {
@@ -32,166 +229,1630 @@ impl DNSDistConsole {
     ///
     /// # Arguments
     ///
-    /// * `host` - A string holding the network address of the DNSDist server (IPv4 or IPv6)
+    /// * `host` - A string holding the network address (IPv4, IPv6 or scoped IPv6 like `fe80::1%eth0`) or the name of the DNSDist server
     /// * `port` - The port of the console on the remote server
-    /// * `key` - An array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes holding the pre-shared key used to encrypt exchanges with the server
+    /// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
     pub fn new(
         host: String,
         port: u16,
-        key: [u8; sodiumoxide::crypto::secretbox::KEYBYTES],
+        key: impl Into<ConsoleKey>,
     ) -> Result<DNSDistConsole, DNSDistConsoleError> {
-        let addr = SocketAddr::new(host.as_str().parse()?, port);
-        let mut stream = match TcpStream::connect_timeout(&addr, Duration::from_secs(5)) {
-            Ok(stream) => stream,
-            Err(err) => return Err(DNSDistConsoleError::IOError(err)),
-        };
+        DNSDistConsole::with_timeout(host, port, key, DEFAULT_CONNECT_TIMEOUT)
+    }
+
+    /// Connects to the first of `addrs` that accepts the connection, trying them in order, and
+    /// returns a DNSDistConsole object, bypassing name resolution entirely
+    ///
+    /// Use `DNSDistConsoleBuilder::addresses` to change the connect timeout or other settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `addrs` - The candidate addresses of the console, in order of preference
+    /// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+    pub fn connect_any(
+        addrs: &[SocketAddr],
+        key: impl Into<ConsoleKey>,
+    ) -> Result<DNSDistConsole, DNSDistConsoleError> {
+        DNSDistConsoleBuilder::new()
+            .addresses(addrs.to_vec())
+            .key(key)
+            .build()
+    }
+
+    /// Connects to a remote DNSDist console over an encrypted connection, waiting at most `connect_timeout`
+    /// for the TCP connection to be established, and returns a DNSDistConsole object
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - A string holding the network address (IPv4, IPv6 or scoped IPv6 like `fe80::1%eth0`) or the name of the DNSDist server
+    /// * `port` - The port of the console on the remote server
+    /// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+    /// * `connect_timeout` - The maximum amount of time to wait for the TCP connection to be established, then for the server to complete the handshake
+    pub fn with_timeout(
+        host: String,
+        port: u16,
+        key: impl Into<ConsoleKey>,
+        connect_timeout: Duration,
+    ) -> Result<DNSDistConsole, DNSDistConsoleError> {
+        DNSDistConsoleBuilder::new()
+            .host(host)
+            .port(port)
+            .key(key)
+            .connect_timeout(connect_timeout)
+            .build()
+    }
+
+    /// Connects to a remote DNSDist console over an encrypted connection using a base64-encoded key,
+    /// as generated by dnsdist's `makeKey()`, and returns a DNSDistConsole object
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - A string holding the network address (IPv4, IPv6 or scoped IPv6 like `fe80::1%eth0`) or the name of the DNSDist server
+    /// * `port` - The port of the console on the remote server
+    /// * `key_b64` - The standard base64 encoding of the pre-shared key used to encrypt exchanges with the server
+    pub fn from_base64_key(
+        host: String,
+        port: u16,
+        key_b64: &str,
+    ) -> Result<DNSDistConsole, DNSDistConsoleError> {
+        DNSDistConsole::new(host, port, key_b64.parse::<ConsoleKey>()?)
+    }
+
+    /// Connects to the remote DNSDist console described by `config`, over an encrypted
+    /// connection, and returns a DNSDistConsole object
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The settings of the console, usually loaded with `ConsoleConfig::load_targets`
+    pub fn from_config(config: &ConsoleConfig) -> Result<DNSDistConsole, DNSDistConsoleError> {
+        DNSDistConsole::new(config.host.clone(), config.port, config.console_key()?)
+    }
+
+    /// Connects to a local DNSDist console listening on a unix domain socket, over an encrypted
+    /// connection, and returns a DNSDistConsole object
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the unix domain socket the console is listening on
+    /// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+    #[cfg(unix)]
+    pub fn connect_unix(
+        path: impl AsRef<Path>,
+        key: impl Into<ConsoleKey>,
+    ) -> Result<DNSDistConsole, DNSDistConsoleError> {
+        let stream = UnixStream::connect(&path)?;
+        let mut console = DNSDistConsole::handshake(
+            Transport::Unix(stream),
+            key.into(),
+            DEFAULT_CONNECT_TIMEOUT,
+        )?;
+        console.origin = Some(Origin::Unix(path.as_ref().to_path_buf()));
+        Ok(console)
+    }
+
+    /// Performs the handshake over an already connected TCP stream and returns a DNSDistConsole
+    /// object, for connections established by the caller, for example through a proxy or an SSH
+    /// tunnel
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - A TCP stream connected to the console of the DNSDist server, on which nothing has been exchanged yet
+    /// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+    pub fn from_stream(
+        stream: TcpStream,
+        key: impl Into<ConsoleKey>,
+    ) -> Result<DNSDistConsole, DNSDistConsoleError> {
+        DNSDistConsole::handshake(Transport::Tcp(stream), key.into(), DEFAULT_CONNECT_TIMEOUT)
+    }
+
+    /// Connects to a remote console that does not use encryption, skipping the handshake and
+    /// exchanging length-prefixed commands and responses in the clear, and returns a
+    /// DNSDistConsole object
+    ///
+    /// dnsdist itself always encrypts its console, so this is only meant to debug the protocol
+    /// against a local test server, with readable packet captures. The connection cannot be
+    /// reconnected, and anyone on the path can read and forge the exchanges.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - A string holding the network address (IPv4, IPv6 or scoped IPv6 like `fe80::1%eth0`) or the name of the server
+    /// * `port` - The port of the console on the remote server
+    #[cfg(feature = "insecure-plaintext")]
+    pub fn connect_plaintext(
+        host: String,
+        port: u16,
+    ) -> Result<DNSDistConsole, DNSDistConsoleError> {
+        let stream = DNSDistConsole::connect(
+            &host,
+            port,
+            AddressFamily::Any,
+            None,
+            DEFAULT_CONNECT_TIMEOUT,
+        )?;
         stream.set_nodelay(true)?;
+        Ok(DNSDistConsole::from_transport(
+            Transport::Tcp(stream),
+            ConsoleProtocol::plaintext(),
+        ))
+    }
 
-        let mut our_nonce: [u8; sodiumoxide::crypto::secretbox::NONCEBYTES] =
-            [0; sodiumoxide::crypto::secretbox::NONCEBYTES];
-        sodiumoxide::randombytes::randombytes_into(&mut our_nonce);
-        stream.write_all(&our_nonce)?;
+    /// Performs the nonce exchange over a newly connected stream, see
+    /// `ConsoleProtocol::handshake`, waiting at most `timeout` for the server
+    fn handshake(
+        mut stream: Transport,
+        key: ConsoleKey,
+        timeout: Duration,
+    ) -> Result<DNSDistConsole, DNSDistConsoleError> {
+        key.validate()?;
 
-        let mut remote_nonce: [u8; sodiumoxide::crypto::secretbox::NONCEBYTES] =
-            [0; sodiumoxide::crypto::secretbox::NONCEBYTES];
-        match stream.read_exact(&mut remote_nonce) {
-            Ok(usize) => usize,
-            Err(e) => {
-                return Err(DNSDistConsoleError::TransportError(format!(
-                    "Error reading nonce: {}",
-                    e.to_string()
-                )))
+        // do not let an unresponsive server block the handshake forever
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let protocol = ConsoleProtocol::handshake(&mut stream, key)?;
+
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(None)?;
+
+        let mut console = DNSDistConsole::from_transport(stream, protocol);
+        console.bytes_sent = sodiumoxide::crypto::secretbox::NONCEBYTES as u64;
+        console.bytes_received = sodiumoxide::crypto::secretbox::NONCEBYTES as u64;
+        Ok(console)
+    }
+
+    /// Creates a DNSDistConsole object from an already connected stream on which the handshake
+    /// has been completed, for example to resume an existing session
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - A TCP stream connected to the console of the DNSDist server
+    /// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+    /// * `reading_nonce` - The nonce that will be used to decrypt the next response from the server
+    /// * `writing_nonce` - The nonce that will be used to encrypt the next command sent to the server
+    pub fn from_parts(
+        stream: TcpStream,
+        key: impl Into<ConsoleKey>,
+        reading_nonce: [u8; sodiumoxide::crypto::secretbox::NONCEBYTES],
+        writing_nonce: [u8; sodiumoxide::crypto::secretbox::NONCEBYTES],
+    ) -> DNSDistConsole {
+        DNSDistConsole::from_transport(
+            Transport::Tcp(stream),
+            ConsoleProtocol::new(key, reading_nonce, writing_nonce),
+        )
+    }
+
+    fn from_transport(stream: Transport, protocol: ConsoleProtocol) -> DNSDistConsole {
+        DNSDistConsole {
+            stream: Some(stream),
+            protocol,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            max_command_size: u32::MAX as usize,
+            poisoned: false,
+            read_only: false,
+            read_timeout: None,
+            write_timeout: None,
+            bytes_sent: 0,
+            bytes_received: 0,
+            origin: None,
+            wire_tap: None,
+        }
+    }
+
+    /// Returns the nonce that will be used to decrypt the next response from the server
+    pub fn reading_nonce(&self) -> [u8; sodiumoxide::crypto::secretbox::NONCEBYTES] {
+        self.protocol.reading_nonce()
+    }
+
+    /// Returns the nonce that will be used to encrypt the next command sent to the server
+    pub fn writing_nonce(&self) -> [u8; sodiumoxide::crypto::secretbox::NONCEBYTES] {
+        self.protocol.writing_nonce()
+    }
+
+    /// Returns the address of the server the connection is established to, which is the one
+    /// that was picked among the resolved addresses when connecting by name. An error is returned
+    /// for connections over a unix domain socket.
+    pub fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        self.transport().peer_addr()
+    }
+
+    /// Consumes the console and returns the underlying TCP stream, without shutting it down, to
+    /// be able to take over the connection. An error is returned, and the connection closed, for
+    /// connections over a unix domain socket.
+    ///
+    /// The state of the encryption is lost with the console, so `reading_nonce` and
+    /// `writing_nonce` have to be saved beforehand to keep talking to the console over the
+    /// stream, for example by passing them back to `from_parts`.
+    pub fn into_stream(mut self) -> Result<TcpStream, DNSDistConsoleError> {
+        match self.stream.take() {
+            Some(Transport::Tcp(stream)) => Ok(stream),
+            #[cfg(unix)]
+            Some(stream) => {
+                // put it back so that it is shut down when the console is dropped
+                self.stream = Some(stream);
+                Err(DNSDistConsoleError::InvalidArgument(
+                    "the console is not connected over TCP".to_string(),
+                ))
             }
+            None => unreachable!("the stream is only taken when consuming the console"),
+        }
+    }
+
+    fn transport(&self) -> &Transport {
+        self.stream
+            .as_ref()
+            .expect("the stream is only taken when consuming the console")
+    }
+
+    fn transport_mut(&mut self) -> &mut Transport {
+        self.stream
+            .as_mut()
+            .expect("the stream is only taken when consuming the console")
+    }
+
+    /// Returns the number of bytes sent over the connection so far, including the handshake and
+    /// the framing, and across reconnections
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent
+    }
+
+    /// Returns the number of bytes received over the connection so far, including the handshake
+    /// and the framing, and across reconnections
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received
+    }
+
+    /// Sets the read and write timeouts of the underlying connection, `None` meaning that
+    /// `send` and `receive` will block until the operation completes
+    ///
+    /// # Arguments
+    ///
+    /// * `read_timeout` - The maximum amount of time to wait for a response to be received
+    /// * `write_timeout` - The maximum amount of time to wait for a command to be sent
+    pub fn set_io_timeouts(
+        &mut self,
+        read_timeout: Option<Duration>,
+        write_timeout: Option<Duration>,
+    ) -> Result<(), DNSDistConsoleError> {
+        self.transport().set_read_timeout(read_timeout)?;
+        self.transport().set_write_timeout(write_timeout)?;
+        self.read_timeout = read_timeout;
+        self.write_timeout = write_timeout;
+        Ok(())
+    }
+
+    /// Closes the current connection and establishes a new one to the same console, with the
+    /// same key, going through a new handshake. The settings of the connection, like the
+    /// timeouts, the maximum response size and the read-only mode, are preserved, and the
+    /// connection is usable again if it was not.
+    ///
+    /// Connections created with `from_stream` or `from_parts` do not know where they come from
+    /// and cannot be reconnected, `DNSDistConsoleError::MissingParameter` is returned in that
+    /// case.
+    pub fn reconnect(&mut self) -> Result<(), DNSDistConsoleError> {
+        let fresh = match &self.origin {
+            Some(Origin::Tcp(builder)) => builder.connect()?,
+            #[cfg(unix)]
+            Some(Origin::Unix(path)) => {
+                DNSDistConsole::connect_unix(path, self.protocol.key_bytes())?
+            }
+            None => return Err(DNSDistConsoleError::MissingParameter("host".to_string())),
+        };
+        self.replace_connection(fresh)
+    }
+
+    /// Establishes a new connection to the same console like `reconnect`, but with a different
+    /// key, and checks that the server accepts it with `ping` before closing the current
+    /// connection, for example to validate a new key during a key rotation
+    ///
+    /// If the new connection fails, or the server does not use the new key, the error is returned
+    /// and the current connection is left untouched, so it can still be used with the old key.
+    /// Otherwise the new key is used from then on, including by later calls to `reconnect`.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The new pre-shared key, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+    pub fn reconnect_with_key(
+        &mut self,
+        key: impl Into<ConsoleKey>,
+    ) -> Result<(), DNSDistConsoleError> {
+        let key = key.into();
+        let mut fresh = match &self.origin {
+            Some(Origin::Tcp(builder)) => builder.as_ref().clone().key(key).build()?,
+            #[cfg(unix)]
+            Some(Origin::Unix(path)) => DNSDistConsole::connect_unix(path, key)?,
+            None => return Err(DNSDistConsoleError::MissingParameter("host".to_string())),
         };
+        fresh.ping()?;
+        self.origin = fresh.origin.take();
+        self.replace_connection(fresh)
+    }
 
-        let mut reading_nonce_buf: [u8; sodiumoxide::crypto::secretbox::NONCEBYTES] =
-            [0; sodiumoxide::crypto::secretbox::NONCEBYTES];
-        reading_nonce_buf[..sodiumoxide::crypto::secretbox::NONCEBYTES / 2]
-            .copy_from_slice(&our_nonce[0..sodiumoxide::crypto::secretbox::NONCEBYTES / 2]);
-        reading_nonce_buf[sodiumoxide::crypto::secretbox::NONCEBYTES / 2..].copy_from_slice(
-            &remote_nonce[sodiumoxide::crypto::secretbox::NONCEBYTES / 2
-                ..sodiumoxide::crypto::secretbox::NONCEBYTES],
-        );
-
-        let mut writing_nonce_buf: [u8; sodiumoxide::crypto::secretbox::NONCEBYTES] =
-            [0; sodiumoxide::crypto::secretbox::NONCEBYTES];
-        writing_nonce_buf[..sodiumoxide::crypto::secretbox::NONCEBYTES / 2]
-            .copy_from_slice(&remote_nonce[0..sodiumoxide::crypto::secretbox::NONCEBYTES / 2]);
-        writing_nonce_buf[sodiumoxide::crypto::secretbox::NONCEBYTES / 2..].copy_from_slice(
-            &our_nonce[sodiumoxide::crypto::secretbox::NONCEBYTES / 2
-                ..sodiumoxide::crypto::secretbox::NONCEBYTES],
-        );
-
-        Ok(DNSDistConsole {
-            stream,
-            writing_nonce: sodiumoxide::crypto::secretbox::Nonce(writing_nonce_buf),
-            reading_nonce: sodiumoxide::crypto::secretbox::Nonce(reading_nonce_buf),
-            secret_key: sodiumoxide::crypto::secretbox::xsalsa20poly1305::Key(key),
-        })
+    /// Replaces the current connection by `fresh`, keeping the settings of the current one
+    fn replace_connection(&mut self, mut fresh: DNSDistConsole) -> Result<(), DNSDistConsoleError> {
+        fresh.set_io_timeouts(self.read_timeout, self.write_timeout)?;
+
+        // the old stream is shut down when `fresh` is dropped
+        std::mem::swap(&mut self.stream, &mut fresh.stream);
+        std::mem::swap(&mut self.protocol, &mut fresh.protocol);
+        self.poisoned = false;
+        self.bytes_sent += fresh.bytes_sent;
+        self.bytes_received += fresh.bytes_received;
+        Ok(())
     }
 
-    /// Sends a command to a dnsdist server over an existing encrypted connection
+    /// Sets a callback invoked with every frame exchanged with the server, exactly as it is on
+    /// the wire: the length prefix followed by the encrypted content, for example to debug
+    /// interoperability issues. `None` removes it.
+    ///
+    /// Frames are passed to the callback right before being written, and once they have been
+    /// read entirely but before being decrypted, so the callback never sees the plaintext. The
+    /// nonces exchanged during the handshake are not passed to it, and it is kept by `reconnect`.
     ///
     /// # Arguments
     ///
-    /// * `command` - A string holding the command to execute
-    pub fn send(&mut self, command: String) -> Result<(), DNSDistConsoleError> {
-        let ciphertext = sodiumoxide::crypto::secretbox::seal(
-            command.as_bytes(),
-            &self.writing_nonce,
-            &self.secret_key,
-        );
+    /// * `tap` - The callback, called with the direction of the frame and its bytes
+    pub fn set_wire_tap(&mut self, tap: Option<WireTap>) {
+        self.wire_tap = tap;
+    }
 
-        let data_size: u32 = ciphertext.len().try_into().unwrap();
+    /// Returns whether the connection can still be used to send commands and receive responses.
+    /// A connection becomes unusable when an error occurs while a frame is being sent or
+    /// received, since the framing and nonces might be out of sync with the server, after which
+    /// `send` and `receive` return `DNSDistConsoleError::ConnectionPoisoned` and a new connection
+    /// has to be established.
+    pub fn is_usable(&self) -> bool {
+        !self.poisoned
+    }
 
-        match self.stream.write_all(&data_size.to_be_bytes()) {
-            Ok(usize) => usize,
-            Err(e) => {
-                return Err(DNSDistConsoleError::TransportError(format!(
-                    "Error writing command size: {}",
-                    e.to_string()
-                )))
+    /// Returns whether the connection is usable and has not been closed by the server, which
+    /// dnsdist does for example when it restarts, checking without waiting
+    pub(crate) fn is_idle(&mut self) -> bool {
+        !self.poisoned && self.transport_mut().is_idle()
+    }
+
+    /// Sets the maximum size of a response, in bytes, that will be accepted from the server
+    /// (default: `DEFAULT_MAX_RESPONSE_SIZE`). A response advertising a larger size is rejected
+    /// with `DNSDistConsoleError::ResponseTooLarge` before anything is allocated, and the
+    /// connection is then no longer usable.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_response_size` - The maximum size of a response, in bytes
+    pub fn set_max_response_size(&mut self, max_response_size: usize) {
+        self.max_response_size = max_response_size;
+    }
+
+    /// Sets the maximum size of a command once encrypted, in bytes, that will be sent to the
+    /// server (default: the largest size the framing allows). A larger command is rejected with
+    /// `DNSDistConsoleError::CommandTooLarge` before anything is written, and the connection
+    /// remains usable.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_command_size` - The maximum size of an encrypted command, in bytes, which is `sodiumoxide::crypto::secretbox::MACBYTES` larger than the command itself
+    pub fn set_max_command_size(&mut self, max_command_size: usize) {
+        self.max_command_size = max_command_size;
+    }
+
+    /// Sets whether only read-only commands can be sent (default: `false`). In read-only mode,
+    /// a command has to be a single call to a function whose name starts with one of
+    /// `READ_ONLY_COMMAND_PREFIXES`, like `showServers()` or `dumpStats()`, with only literal
+    /// arguments, otherwise it is rejected with `DNSDistConsoleError::CommandRejected` without
+    /// being sent.
+    ///
+    /// This is only meant to prevent accidents, the console ACL and key are what actually
+    /// protect the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `read_only` - Whether to reject commands that are not known to be read-only
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Executes a command over an existing encrypted connection and returns the response,
+    /// so that several commands can be executed without going through a new handshake every time
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to execute, as a `&str` or a `String`
+    ///
+    /// ```no_run
+    /// # use lib_rs_dnsdist_console::DNSDistConsole;
+    /// # fn main() -> Result<(), lib_rs_dnsdist_console::DNSDistConsoleError> {
+    /// # let key = [0_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    /// let mut console = DNSDistConsole::new("127.0.0.1".to_string(), 5199, key)?;
+    /// let commands = vec!["showVersion()", "showServers()"];
+    /// for command in commands {
+    ///     println!("{}", console.execute(command)?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn execute(&mut self, command: impl AsRef<str>) -> Result<String, DNSDistConsoleError> {
+        self.send(command)?;
+        self.receive()
+    }
+
+    /// Reads a Lua snippet from the file at `path` and executes it as a single command, returning
+    /// the response
+    ///
+    /// The content of the file is sent exactly as it is, without trimming, since whitespace can
+    /// matter in Lua strings. A file that cannot be read, or is not valid UTF-8, is reported as
+    /// `DNSDistConsoleError::IOError` and nothing is sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file holding the snippet
+    pub fn execute_file(&mut self, path: impl AsRef<Path>) -> Result<String, DNSDistConsoleError> {
+        let command = std::fs::read_to_string(path)?;
+        self.execute(command)
+    }
+
+    /// Executes a command like `execute` and also returns how long the round-trip took, measured
+    /// from right before sending the command to right after receiving the response, for example
+    /// to monitor the latency of the console
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to execute, as a `&str` or a `String`
+    pub fn execute_timed(
+        &mut self,
+        command: impl AsRef<str>,
+    ) -> Result<(String, Duration), DNSDistConsoleError> {
+        let start = Instant::now();
+        let response = self.execute(command)?;
+        Ok((response, start.elapsed()))
+    }
+
+    /// Executes a command like `execute`, but returns `DNSDistConsoleError::CommandError` holding
+    /// the response when it looks like the command failed on the server
+    ///
+    /// dnsdist reports Lua errors in-band, as a regular response, so this is a heuristic: a
+    /// response is considered an error if it starts with `Error: `, which is how dnsdist prefixes
+    /// the errors it catches, or if it holds a Lua `stack traceback:`. A command whose legitimate
+    /// output matches, like a `print` of such a text, is reported as an error too. The connection
+    /// remains usable either way.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to execute, as a `&str` or a `String`
+    pub fn execute_checked(
+        &mut self,
+        command: impl AsRef<str>,
+    ) -> Result<String, DNSDistConsoleError> {
+        let response = self.execute(command)?;
+        if command::is_error_response(&response) {
+            return Err(DNSDistConsoleError::CommandError(response));
+        }
+        Ok(response)
+    }
+
+    /// Executes several commands in order over an existing encrypted connection and returns their
+    /// responses, in the same order
+    ///
+    /// Execution stops at the first command that fails, and the error is then
+    /// `DNSDistConsoleError::BatchCommandFailed` holding the index of that command in `commands`
+    /// and the error itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `commands` - The commands to execute, as `&str` or `String`
+    pub fn execute_batch<S: AsRef<str>>(
+        &mut self,
+        commands: &[S],
+    ) -> Result<Vec<String>, DNSDistConsoleError> {
+        let mut responses = Vec::with_capacity(commands.len());
+        for (idx, command) in commands.iter().enumerate() {
+            match self.execute(command) {
+                Ok(response) => responses.push(response),
+                Err(e) => return Err(DNSDistConsoleError::BatchCommandFailed(idx, Box::new(e))),
             }
-        };
-        match self.stream.write_all(&ciphertext) {
+        }
+        Ok(responses)
+    }
+
+    /// Runs `showSecurityStatus()` and returns the security status of the server, as found by
+    /// its security polling, for health checks
+    pub fn security_status(&mut self) -> Result<SecurityStatus, DNSDistConsoleError> {
+        let output = self.execute("showSecurityStatus()")?;
+        Ok(parse_security_status(&output)?)
+    }
+
+    /// Executes several commands in order over an existing encrypted connection like
+    /// `execute_batch`, but sends the commands without waiting for the previous responses, then
+    /// reads all the responses, saving a round-trip per command
+    ///
+    /// dnsdist reads and executes console commands one at a time, in order, and answers each of
+    /// them before reading the next one, so pipelined commands are serialized by the server and
+    /// their responses come back in the same order. The server does not read new commands while
+    /// it cannot write its responses, so commands are sent in windows of at most 64 KiB, whose
+    /// responses are read before the next window is sent: this way both sides can never be
+    /// blocked writing at the same time.
+    ///
+    /// If a command fails, the error is `DNSDistConsoleError::BatchCommandFailed` holding the
+    /// index of that command in `commands` and the error itself. The responses to the commands
+    /// already sent are then not read, so the connection is no longer usable.
+    ///
+    /// # Arguments
+    ///
+    /// * `commands` - The commands to execute, as `&str` or `String`
+    pub fn pipeline<S: AsRef<str>>(
+        &mut self,
+        commands: &[S],
+    ) -> Result<Vec<String>, DNSDistConsoleError> {
+        let mut responses = Vec::with_capacity(commands.len());
+        let mut sent = 0;
+        while sent < commands.len() {
+            let window_start = sent;
+            let mut window_size = 0;
+            while sent < commands.len()
+                && (sent == window_start
+                    || window_size + commands[sent].as_ref().len() <= PIPELINE_WINDOW)
+            {
+                if let Err(e) = self.send(&commands[sent]) {
+                    return Err(self.pipeline_failed(sent, sent > window_start, e));
+                }
+                window_size += commands[sent].as_ref().len();
+                sent += 1;
+            }
+
+            for idx in window_start..sent {
+                match self.receive() {
+                    Ok(response) => responses.push(response),
+                    Err(e) => return Err(self.pipeline_failed(idx, idx + 1 < sent, e)),
+                }
+            }
+        }
+        Ok(responses)
+    }
+
+    /// Wraps the error of command `idx` of a pipeline, marking the connection as unusable if
+    /// responses are still pending
+    fn pipeline_failed(
+        &mut self,
+        idx: usize,
+        pending: bool,
+        e: DNSDistConsoleError,
+    ) -> DNSDistConsoleError {
+        if pending {
+            self.poisoned = true;
+        }
+        DNSDistConsoleError::BatchCommandFailed(idx, Box::new(e))
+    }
+
+    /// Checks that the connection is still alive with a cheap round-trip: an empty command is
+    /// sent, which dnsdist executes without any effect, and its response has to come back and
+    /// decrypt. This works in read-only mode as well.
+    pub fn ping(&mut self) -> Result<(), DNSDistConsoleError> {
+        self.send_frame("")?;
+        self.receive_bytes()?;
+        Ok(())
+    }
+
+    /// Executes a typed query, like `ServerStatusQuery`, and returns its parsed result
+    ///
+    /// The expressions built by queries only read the state of the server, so they are accepted
+    /// in read-only mode as well. They are still subject to the limit set with
+    /// `set_max_command_size`, like any command sent with `send`. A response reporting a Lua error, for example because the
+    /// server does not exist, is returned as `DNSDistConsoleError::CommandError`.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The query to execute
+    ///
+    /// ```no_run
+    /// # use lib_rs_dnsdist_console::{DNSDistConsole, ServerStatusQuery};
+    /// # fn main() -> Result<(), lib_rs_dnsdist_console::DNSDistConsoleError> {
+    /// # let key = [0_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    /// let mut console = DNSDistConsole::new("127.0.0.1".to_string(), 5199, key)?;
+    /// let status = console.query(&ServerStatusQuery::new(0))?;
+    /// println!("{} is {}", status.name, if status.up { "up" } else { "down" });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn query<Q: Query>(&mut self, query: &Q) -> Result<Q::Output, DNSDistConsoleError> {
+        let expression = query.expression();
+        self.check_command_size(&expression)?;
+        // bypass the read-only filter on purpose: the expressions of the queries only read the
+        // state of the server, but would not pass the filter made for console commands
+        self.send_frame(&expression)?;
+        let response = self.receive()?;
+        if command::is_error_response(&response) {
+            return Err(DNSDistConsoleError::CommandError(response));
+        }
+        Ok(query.parse(&response)?)
+    }
+
+    /// Runs `showVersion()` and returns the version of the server, for example to only use
+    /// commands available in recent versions of dnsdist
+    ///
+    /// ```no_run
+    /// # use lib_rs_dnsdist_console::{DNSDistConsole, ServerVersion};
+    /// # fn main() -> Result<(), lib_rs_dnsdist_console::DNSDistConsoleError> {
+    /// # let key = [0_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    /// let mut console = DNSDistConsole::new("127.0.0.1".to_string(), 5199, key)?;
+    /// if console.server_version()? >= ServerVersion::new(1, 7, 0) {
+    ///     println!("{}", console.execute("showServers({showUUIDs=true})")?);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn server_version(&mut self) -> Result<ServerVersion, DNSDistConsoleError> {
+        let output = self.execute("showVersion()")?;
+        Ok(parse_version(&output)?)
+    }
+
+    /// Sends a command over an existing encrypted connection, then reads and drops its response,
+    /// so that the connection is ready for the next command
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to execute, as a `&str` or a `String`
+    pub fn send_and_discard(
+        &mut self,
+        command: impl AsRef<str>,
+    ) -> Result<(), DNSDistConsoleError> {
+        self.send(command)?;
+        self.receive_bytes()?;
+        Ok(())
+    }
+
+    /// Sends a command to a dnsdist server over an existing encrypted connection
+    ///
+    /// dnsdist answers every command with exactly one response, which has to be read with
+    /// `receive` or `receive_bytes` before the response to the next command can be: `send` does
+    /// not wait for it, so sending several commands in a row without reading their responses
+    /// means that `receive` will return the response to the first one. Use `execute` to get the
+    /// response right away, or `send_and_discard` when it is of no interest.
+    ///
+    /// If the server has closed the connection, `DNSDistConsoleError::ConnectionClosed` is
+    /// returned and the connection has to be re-established, for example with `reconnect`.
+    ///
+    /// The command is sent as-is, without a trailing newline: dnsdist evaluates the whole content
+    /// of a frame as soon as it has received it, and its own client does not add one either. A
+    /// server that never answers is therefore not waiting for a newline, it is usually busy with a
+    /// slow command or not a dnsdist console at all; a read timeout turns that into an error.
+    ///
+    /// The size of the encrypted command has to fit in the 32-bit length prefix of the frame,
+    /// so commands longer than `MAX_COMMAND_SIZE` bytes are rejected with
+    /// `DNSDistConsoleError::CommandTooLarge`, as well as commands larger than the limit set with
+    /// `set_max_command_size`.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to execute, as a `&str` or a `String`
+    pub fn send(&mut self, command: impl AsRef<str>) -> Result<(), DNSDistConsoleError> {
+        let command = command.as_ref();
+        if self.poisoned {
+            return Err(DNSDistConsoleError::ConnectionPoisoned);
+        }
+        if self.read_only && !command::is_read_only(command) {
+            return Err(DNSDistConsoleError::CommandRejected(command.to_string()));
+        }
+        self.check_command_size(command)?;
+        self.send_frame(command)
+    }
+
+    /// Checks that `command` fits in the limit set with `set_max_command_size` once encrypted
+    fn check_command_size(&self, command: &str) -> Result<(), DNSDistConsoleError> {
+        if command
+            .len()
+            .saturating_add(sodiumoxide::crypto::secretbox::MACBYTES)
+            > self.max_command_size
+        {
+            return Err(DNSDistConsoleError::CommandTooLarge(command.len()));
+        }
+        Ok(())
+    }
+
+    /// Encrypts and sends `command`, without checking it against the read-only mode or the size
+    /// limit
+    fn send_frame(&mut self, command: &str) -> Result<(), DNSDistConsoleError> {
+        if self.poisoned {
+            return Err(DNSDistConsoleError::ConnectionPoisoned);
+        }
+
+        // write the whole frame at once, so that the length and the ciphertext are
+        // not sent in separate segments
+        let frame = self.protocol.encode_command(command)?;
+        if let Some(tap) = self.wire_tap.as_mut() {
+            tap(Direction::Sent, &frame);
+        }
+        match self.transport_mut().write_all(&frame) {
             Ok(usize) => usize,
             Err(e) => {
-                return Err(DNSDistConsoleError::TransportError(format!(
-                    "Error writing command: {}",
-                    e.to_string()
-                )))
+                // part of the frame might have been sent already
+                self.poisoned = true;
+                return match e.kind() {
+                    ErrorKind::BrokenPipe | ErrorKind::ConnectionReset => {
+                        Err(DNSDistConsoleError::ConnectionClosed)
+                    }
+                    _ => Err(DNSDistConsoleError::from_transport(Phase::Send, e)),
+                };
             }
         };
-        DNSDistConsole::increment_nonce_inplace(&mut self.writing_nonce.0);
+        self.bytes_sent += frame.len() as u64;
 
         Ok(())
     }
 
     /// Receives a response from a dnsdist server over an existing encrypted connection
     pub fn receive(&mut self) -> Result<String, DNSDistConsoleError> {
-        let mut len_buffer: [u8; 4] = [0; 4];
-        match self.stream.read_exact(&mut len_buffer) {
-            Ok(usize) => usize,
+        let cleartext = self.receive_bytes()?;
+        Ok(String::from_utf8(cleartext)?)
+    }
+
+    /// Receives a response like `receive`, but into `buf`, whose previous content is cleared and
+    /// whose allocation is reused for the encrypted frame, then for the response, so that a loop
+    /// receiving many responses does not allocate once `buf` is large enough
+    ///
+    /// If the response is not valid UTF-8, `DNSDistConsoleError::Utf8Error` is returned, holding
+    /// the decrypted bytes, and `buf` is left empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `buf` - The buffer the response is written to
+    pub fn receive_into(&mut self, buf: &mut String) -> Result<(), DNSDistConsoleError> {
+        let mut frame = std::mem::take(buf).into_bytes();
+        let start = match self.receive_frame_into(&mut frame) {
+            Ok(start) => start,
             Err(e) => {
-                return Err(DNSDistConsoleError::TransportError(format!(
-                    "Error reading response size: {}",
-                    e.to_string()
-                )))
+                frame.clear();
+                // an empty buffer is always valid UTF-8, this only keeps the allocation around
+                *buf = String::from_utf8(frame).unwrap_or_default();
+                return Err(e);
             }
         };
+        frame.drain(..start);
+        *buf = String::from_utf8(frame)?;
+        Ok(())
+    }
 
-        let data_size = u32::from_be_bytes(len_buffer);
+    /// Returns an iterator over the responses received on the connection, decrypted in turn,
+    /// until the server closes the connection or the read timeout set with `set_io_timeouts`
+    /// elapses
+    ///
+    /// dnsdist currently answers every command with exactly one response, so this is only
+    /// useful for commands producing several frames. Without a read timeout, the iterator blocks
+    /// until the connection is closed. When the timeout elapses before the next frame has
+    /// started arriving, the connection remains usable; any other error, including a timeout in
+    /// the middle of a frame, is yielded, then ends the iteration. Check `is_usable` before
+    /// using the connection again once the iteration has ended.
+    pub fn responses(&mut self) -> Responses<'_> {
+        Responses {
+            console: self,
+            done: false,
+        }
+    }
 
-        let mut reading_buffer = vec![0_u8; data_size.try_into().unwrap()];
+    /// Receives a response like `receive`, waiting at most `timeout` for it regardless of the
+    /// read timeout of the connection, which is restored afterwards
+    ///
+    /// `DNSDistConsoleError::Timeout` is returned if the response did not arrive in time. The
+    /// response is then still pending, so the connection is no longer usable.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - The maximum amount of time to wait for the response
+    pub fn receive_within(&mut self, timeout: Duration) -> Result<String, DNSDistConsoleError> {
+        self.transport().set_read_timeout(Some(timeout))?;
+        let result = self.receive();
+        self.transport().set_read_timeout(self.read_timeout)?;
+        match result {
+            Err(DNSDistConsoleError::TransportError(Phase::Receive, e))
+                if e.kind() == ErrorKind::TimedOut =>
+            {
+                Err(DNSDistConsoleError::Timeout)
+            }
+            result => result,
+        }
+    }
+
+    /// Receives a response from a dnsdist server over an existing encrypted connection, and
+    /// returns the decrypted bytes without checking that they are valid UTF-8
+    ///
+    /// If the first response received on the connection cannot be decrypted, the error is
+    /// `DNSDistConsoleError::KeyMismatch`, since the key not matching the one of the server is by
+    /// far the most likely cause. dnsdist itself does not reply to a command encrypted with the
+    /// wrong key but closes the connection, which is reported as
+    /// `DNSDistConsoleError::ClosedBeforeKeyConfirmed` as long as no response has been
+    /// decrypted. Later failures are reported as
+    /// `DNSDistConsoleError::DecryptionError`. A frame too short to hold the authentication tag,
+    /// including an empty one, is reported as `DNSDistConsoleError::MalformedFrame`.
+    pub fn receive_bytes(&mut self) -> Result<Vec<u8>, DNSDistConsoleError> {
+        let (mut frame, start) = self.receive_frame()?;
+        frame.drain(..start);
+        Ok(frame)
+    }
+
+    /// Receives a response from a dnsdist server over an existing encrypted connection, and
+    /// writes the decrypted bytes to `writer`, returning the size of the response
+    ///
+    /// dnsdist sends every response as a single encrypted frame, which has to be received
+    /// entirely before it can be authenticated and decrypted, so the response is never streamed
+    /// from the network. It is however decrypted in place and written to `writer` in chunks, so
+    /// the memory used is the size of the response, instead of twice that size for
+    /// `receive_bytes` followed by a write.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where the decrypted response is written to
+    pub fn receive_to_writer(
+        &mut self,
+        writer: &mut impl Write,
+    ) -> Result<usize, DNSDistConsoleError> {
+        let (frame, start) = self.receive_frame()?;
+        let response = &frame[start..];
+        for chunk in response.chunks(WRITER_CHUNK_SIZE) {
+            writer.write_all(chunk)?;
+        }
+        Ok(response.len())
+    }
 
-        match self.stream.read_exact(&mut reading_buffer) {
+    /// Reads a whole frame then decrypts it in place, returning the frame and the offset of the
+    /// decrypted response in it: the response follows the first
+    /// `sodiumoxide::crypto::secretbox::MACBYTES` bytes, where the authentication tag was, or
+    /// starts right away on a plaintext connection
+    fn receive_frame(&mut self) -> Result<(Vec<u8>, usize), DNSDistConsoleError> {
+        let mut frame = Vec::new();
+        let start = self.receive_frame_into(&mut frame)?;
+        Ok((frame, start))
+    }
+
+    /// Reads a whole frame into `frame`, replacing its content but reusing its allocation, then
+    /// decrypts it in place like `receive_frame` and returns the offset of the response in it
+    fn receive_frame_into(&mut self, frame: &mut Vec<u8>) -> Result<usize, DNSDistConsoleError> {
+        match self.receive_next_frame_into(frame)? {
+            Some(start) => Ok(start),
+            None => {
+                // the response we are waiting for might still arrive later
+                self.poisoned = true;
+                Err(DNSDistConsoleError::TransportError(
+                    Phase::Receive,
+                    std::io::Error::new(ErrorKind::TimedOut, "timed out"),
+                ))
+            }
+        }
+    }
+
+    /// Reads a whole frame into `frame` like `receive_frame_into`, returning `None` without
+    /// poisoning the connection if the read timeout elapses before any byte of the frame has
+    /// been received, since the connection is then still in sync with the server
+    fn receive_next_frame_into(
+        &mut self,
+        frame: &mut Vec<u8>,
+    ) -> Result<Option<usize>, DNSDistConsoleError> {
+        if self.poisoned {
+            return Err(DNSDistConsoleError::ConnectionPoisoned);
+        }
+        let mut len_buffer: [u8; FRAME_LENGTH_SIZE] = [0; FRAME_LENGTH_SIZE];
+        let mut read = 0;
+        while read < len_buffer.len() {
+            match self.transport_mut().read(&mut len_buffer[read..]) {
+                Ok(0) => {
+                    self.poisoned = true;
+                    return Err(self.receive_error(std::io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "failed to fill whole buffer",
+                    )));
+                }
+                Ok(size) => read += size,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e)
+                    if read == 0
+                        && (e.kind() == ErrorKind::WouldBlock
+                            || e.kind() == ErrorKind::TimedOut) =>
+                {
+                    return Ok(None)
+                }
+                Err(e) => {
+                    self.poisoned = true;
+                    return Err(self.receive_error(e));
+                }
+            }
+        }
+
+        self.bytes_received += len_buffer.len() as u64;
+
+        let data_size: usize = protocol::decode_frame_length(len_buffer)
+            .try_into()
+            .unwrap_or(usize::MAX);
+        if data_size > self.max_response_size {
+            // the response is left unread, so we are no longer in sync with the server
+            self.poisoned = true;
+            return Err(DNSDistConsoleError::ResponseTooLarge(data_size));
+        }
+
+        frame.clear();
+        frame.resize(data_size, 0);
+
+        match self.transport_mut().read_exact(frame) {
             Ok(usize) => usize,
             Err(e) => {
-                return Err(DNSDistConsoleError::TransportError(format!(
-                    "Error reading response: {}",
-                    e.to_string()
-                )))
+                self.poisoned = true;
+                return Err(DNSDistConsoleError::from_transport(Phase::Receive, e));
+            }
+        };
+        self.bytes_received += frame.len() as u64;
+        if let Some(tap) = self.wire_tap.as_mut() {
+            let mut raw = Vec::with_capacity(FRAME_LENGTH_SIZE + frame.len());
+            raw.extend_from_slice(&len_buffer);
+            raw.extend_from_slice(frame);
+            tap(Direction::Received, &raw);
+        }
+
+        self.protocol.open_in_place(frame).map(Some)
+    }
+
+    /// Wraps an I/O error that occurred while waiting for the length of a response. dnsdist
+    /// closes the connection without replying to a command it cannot decrypt, so the connection
+    /// being closed before any response could be decrypted is reported as
+    /// `DNSDistConsoleError::ClosedBeforeKeyConfirmed`. A connection closed in the middle of a
+    /// response is not, since the server did start replying.
+    fn receive_error(&self, err: std::io::Error) -> DNSDistConsoleError {
+        match err.kind() {
+            ErrorKind::UnexpectedEof
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+                if !self.protocol.key_confirmed() =>
+            {
+                DNSDistConsoleError::ClosedBeforeKeyConfirmed
+            }
+            _ => DNSDistConsoleError::from_transport(Phase::Receive, err),
+        }
+    }
+
+    /// Connects to `host`, which is either a literal IP address, a scoped IPv6 address like
+    /// `fe80::1%eth0`, or a name that will be resolved,
+    /// trying every resolved address in turn until one succeeds. If `bind` is set, the connection
+    /// is made from that local address, skipping resolved addresses of a different family.
+    fn connect(
+        host: &str,
+        port: u16,
+        family: AddressFamily,
+        bind: Option<&SocketAddr>,
+        timeout: Duration,
+    ) -> Result<TcpStream, DNSDistConsoleError> {
+        // catch obvious mistakes before they turn into confusing resolution or connection errors
+        if host.is_empty() {
+            return Err(DNSDistConsoleError::InvalidArgument(
+                "the host is empty".to_string(),
+            ));
+        }
+        if port == 0 {
+            return Err(DNSDistConsoleError::InvalidArgument(
+                "the port is 0".to_string(),
+            ));
+        }
+
+        let literal = match host.parse::<IpAddr>() {
+            Ok(ip) => Some(SocketAddr::new(ip, port)),
+            Err(_) => address::parse_scoped_ipv6(host, port)?,
+        };
+        if let Some(addr) = literal {
+            if !family.matches(&addr) {
+                return Err(DNSDistConsoleError::InvalidArgument(format!(
+                    "{} is not an {} address",
+                    host, family
+                )));
             }
+            return DNSDistConsole::connect_addr(&addr, bind, timeout);
+        }
+
+        let addrs = match (host, port).to_socket_addrs() {
+            Ok(addrs) => addrs,
+            Err(e) => return Err(DNSDistConsoleError::from_transport(Phase::Connect, e)),
         };
-        let cleartext = sodiumoxide::crypto::secretbox::open(
-            &reading_buffer,
-            &self.reading_nonce,
-            &self.secret_key,
-        );
-        DNSDistConsole::increment_nonce_inplace(&mut self.reading_nonce.0);
+        let mut last_error = None;
+        for addr in addrs.filter(|addr| family.matches(addr)) {
+            if let Some(bind) = bind {
+                if bind.is_ipv4() != addr.is_ipv4() {
+                    continue;
+                }
+            }
+            match DNSDistConsole::connect_addr(&addr, bind, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_error = Some(err),
+            }
+        }
 
-        Ok(String::from_utf8(cleartext.unwrap()).unwrap())
+        match last_error {
+            Some(err) => Err(err),
+            None => Err(DNSDistConsoleError::TransportError(
+                Phase::Connect,
+                std::io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("no {} address found for {}", family, host),
+                ),
+            )),
+        }
     }
 
-    fn increment_nonce_inplace(nonce: &mut [u8]) {
-        if nonce.len() < 4 {
-            panic!("invalid nonce size");
+    /// Tries every address of `addrs` in order until one accepts the connection
+    fn connect_first(
+        addrs: &[SocketAddr],
+        bind: Option<&SocketAddr>,
+        timeout: Duration,
+    ) -> Result<TcpStream, DNSDistConsoleError> {
+        let mut last_error =
+            DNSDistConsoleError::InvalidArgument("no address to connect to".to_string());
+        for addr in addrs {
+            match DNSDistConsole::connect_addr(addr, bind, timeout) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => last_error = err,
+            }
         }
-        let ptr: *mut u8 = nonce.as_mut_ptr();
-        let ptr: *mut u32 = ptr as *mut u32;
-        unsafe {
-            let mut value = (*ptr).to_be();
-            value += 1;
-            *ptr = u32::from_be(value);
+        Err(last_error)
+    }
+
+    fn connect_addr(
+        addr: &SocketAddr,
+        bind: Option<&SocketAddr>,
+        timeout: Duration,
+    ) -> Result<TcpStream, DNSDistConsoleError> {
+        let result = match bind {
+            Some(local) => sockopt::connect_from(local, addr, timeout),
+            None => TcpStream::connect_timeout(addr, timeout),
+        };
+        match result {
+            Ok(stream) => Ok(stream),
+            Err(e) => Err(DNSDistConsoleError::from_transport(Phase::Connect, e)),
         }
     }
 }
 
+/// An iterator over the responses received on a connection, see `DNSDistConsole::responses`
+pub struct Responses<'a> {
+    console: &'a mut DNSDistConsole,
+    done: bool,
+}
+
+impl Iterator for Responses<'_> {
+    type Item = Result<String, DNSDistConsoleError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut frame = Vec::new();
+        match self.console.receive_next_frame_into(&mut frame) {
+            Ok(Some(start)) => {
+                frame.drain(..start);
+                Some(String::from_utf8(frame).map_err(DNSDistConsoleError::from))
+            }
+            // nothing more came in time, the connection can still be used
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(DNSDistConsoleError::TransportError(Phase::Receive, e))
+                if e.kind() == ErrorKind::UnexpectedEof =>
+            {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Written by hand instead of derived, so that the key and the nonces never end up in logs
+impl fmt::Debug for DNSDistConsole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DNSDistConsole")
+            .field("peer", &self.transport().peer_description())
+            .field("usable", &!self.poisoned)
+            .field("read_only", &self.read_only)
+            .field("max_response_size", &self.max_response_size)
+            .field("max_command_size", &self.max_command_size)
+            .field("wire_tap", &self.wire_tap.is_some())
+            .field("secret_key", &"***")
+            .field("nonces", &"***")
+            .finish()
+    }
+}
+
+impl Drop for DNSDistConsole {
+    /// Shuts the connection down so that dnsdist can release the console session right away
+    fn drop(&mut self) {
+        if let Some(stream) = &self.stream {
+            let _ = stream.shutdown();
+        }
+    }
+}
+
+/// A builder to configure and open a connection to a dnsdist console
+///
+/// ```no_run
+/// # use lib_rs_dnsdist_console::DNSDistConsoleBuilder;
+/// # use std::time::Duration;
+/// # fn main() -> Result<(), lib_rs_dnsdist_console::DNSDistConsoleError> {
+/// # let key = [0_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+/// let mut console = DNSDistConsoleBuilder::new()
+///     .host("127.0.0.1".to_string())
+///     .port(5199)
+///     .key(key)
+///     .connect_timeout(Duration::from_secs(2))
+///     .read_timeout(Some(Duration::from_secs(10)))
+///     .build()?;
+/// console.send("showVersion()")?;
+/// println!("{}", console.receive()?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct DNSDistConsoleBuilder {
+    host: Option<String>,
+    port: Option<u16>,
+    key: Option<ConsoleKey>,
+    connect_timeout: Duration,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    nodelay: bool,
+    keepalive: Option<Duration>,
+    bind: Option<SocketAddr>,
+    addresses: Option<Vec<SocketAddr>>,
+    read_only: bool,
+    max_response_size: usize,
+    max_command_size: usize,
+    proxy_header: Option<ProxyHeader>,
+    address_family: AddressFamily,
+    handshake_timeout: Option<Duration>,
+}
+
+/// The settings shared by many connections to the same console, see `DNSDistConsoleBuilder::connect`
+///
+/// A live connection cannot be cloned, but its settings can: configure a template once, then
+/// open as many connections from it as needed, for example one per thread. Every setting of a
+/// connection, including the timeouts, the size limits and the read-only mode, can be set on the
+/// template.
+///
+/// ```no_run
+/// # use lib_rs_dnsdist_console::ConsoleTemplate;
+/// # fn main() -> Result<(), lib_rs_dnsdist_console::DNSDistConsoleError> {
+/// # let key = [0_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+/// let template = ConsoleTemplate::new()
+///     .host("127.0.0.1".to_string())
+///     .port(5199)
+///     .key(key)
+///     .read_only(true);
+/// let mut first = template.connect()?;
+/// let mut second = template.connect()?;
+/// # Ok(())
+/// # }
+/// ```
+pub type ConsoleTemplate = DNSDistConsoleBuilder;
+
+impl Default for DNSDistConsoleBuilder {
+    fn default() -> Self {
+        DNSDistConsoleBuilder::new()
+    }
+}
+
+impl DNSDistConsoleBuilder {
+    /// Creates a new builder, the host and port (or the addresses) and the key have to be set before
+    /// calling `build`
+    pub fn new() -> DNSDistConsoleBuilder {
+        DNSDistConsoleBuilder {
+            host: None,
+            port: None,
+            key: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout: None,
+            write_timeout: None,
+            nodelay: true,
+            keepalive: Some(DEFAULT_KEEPALIVE_INTERVAL),
+            bind: None,
+            addresses: None,
+            read_only: false,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            max_command_size: u32::MAX as usize,
+            proxy_header: None,
+            address_family: AddressFamily::Any,
+            handshake_timeout: None,
+        }
+    }
+
+    /// Sets the network address (IPv4 or IPv6) or the name of the DNSDist server
+    pub fn host(mut self, host: String) -> DNSDistConsoleBuilder {
+        self.host = Some(host);
+        self
+    }
+
+    /// Sets the port of the console on the remote server
+    pub fn port(mut self, port: u16) -> DNSDistConsoleBuilder {
+        self.port = Some(port);
+        self
+    }
+
+    /// Sets the family of the addresses the host is resolved to that connections are attempted
+    /// to (default: `AddressFamily::Any`), for example to only use IPv4 when the console ACL
+    /// only allows an IPv4 range. A host that is a literal address of another family is rejected
+    /// with `DNSDistConsoleError::InvalidArgument`.
+    pub fn address_family(mut self, family: AddressFamily) -> DNSDistConsoleBuilder {
+        self.address_family = family;
+        self
+    }
+
+    /// Sets an ordered list of addresses to try instead of resolving the host, for example when
+    /// the candidates come from service discovery. The first one accepting the connection within
+    /// the connect timeout is used, and the host and port do not need to be set.
+    pub fn addresses(mut self, addrs: Vec<SocketAddr>) -> DNSDistConsoleBuilder {
+        self.addresses = Some(addrs);
+        self
+    }
+
+    /// Sets the pre-shared key used to encrypt exchanges with the server
+    pub fn key(mut self, key: impl Into<ConsoleKey>) -> DNSDistConsoleBuilder {
+        self.key = Some(key.into());
+        self
+    }
+
+    /// Sets the maximum amount of time to wait for the TCP connection to be established, then for
+    /// the server to complete the handshake unless a different `handshake_timeout` is set
+    /// (default: `DEFAULT_CONNECT_TIMEOUT`)
+    pub fn connect_timeout(mut self, timeout: Duration) -> DNSDistConsoleBuilder {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum amount of time to wait for the server to complete the nonce exchange
+    /// once the TCP connection has been established (default: the connect timeout), for example
+    /// a longer one when a slow proxy sits in front of the console. It does not apply to the
+    /// responses to commands, see `read_timeout`.
+    pub fn handshake_timeout(mut self, timeout: Duration) -> DNSDistConsoleBuilder {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum amount of time to wait for a response once the connection has been
+    /// established, `None` meaning no limit (default: `None`)
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> DNSDistConsoleBuilder {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum amount of time to wait for a command to be sent once the connection has
+    /// been established, `None` meaning no limit (default: `None`)
+    pub fn write_timeout(mut self, timeout: Option<Duration>) -> DNSDistConsoleBuilder {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Sets whether Nagle's algorithm should be disabled on the TCP connection (default: `true`)
+    ///
+    /// Disabling it is right for interactive use, where every command waits for its response.
+    /// Leaving it enabled lets the kernel coalesce frames when sending many small commands with
+    /// `pipeline`, reducing the number of segments sent.
+    pub fn nodelay(mut self, nodelay: bool) -> DNSDistConsoleBuilder {
+        self.nodelay = nodelay;
+        self
+    }
+
+    /// Sets the TCP keepalive interval, so that a connection silently dropped by a firewall while
+    /// idle is detected and `send` or `receive` fail with a `DNSDistConsoleError::TransportError`
+    /// instead of hanging, `None` disabling keepalive (default: `DEFAULT_KEEPALIVE_INTERVAL`).
+    /// The setting is ignored on non-unix platforms.
+    pub fn keepalive(mut self, interval: Option<Duration>) -> DNSDistConsoleBuilder {
+        self.keepalive = interval;
+        self
+    }
+
+    /// Sets the local address the connection is made from, for example when the console ACL only
+    /// allows one of the addresses of a multi-homed host. The port can be 0 to let the system
+    /// pick one. Only supported on unix platforms.
+    pub fn bind(mut self, local: SocketAddr) -> DNSDistConsoleBuilder {
+        self.bind = Some(local);
+        self
+    }
+
+    /// Sets a PROXY protocol header to send right after connecting, before the handshake, for
+    /// when the console is reached through a proxy expecting one, so that the console ACL sees
+    /// the address of the real client. The connection fails with
+    /// `DNSDistConsoleError::InvalidArgument` if the addresses of the header are not of the same
+    /// family.
+    pub fn proxy_header(mut self, header: ProxyHeader) -> DNSDistConsoleBuilder {
+        self.proxy_header = Some(header);
+        self
+    }
+
+    /// Sets whether the connections only accept read-only commands (default: `false`), see
+    /// `DNSDistConsole::set_read_only`
+    pub fn read_only(mut self, read_only: bool) -> DNSDistConsoleBuilder {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Sets the maximum size of a response (default: `DEFAULT_MAX_RESPONSE_SIZE`), see
+    /// `DNSDistConsole::set_max_response_size`
+    pub fn max_response_size(mut self, max_response_size: usize) -> DNSDistConsoleBuilder {
+        self.max_response_size = max_response_size;
+        self
+    }
+
+    /// Sets the maximum size of a command once encrypted (default: the largest size the framing
+    /// allows), see `DNSDistConsole::set_max_command_size`
+    pub fn max_command_size(mut self, max_command_size: usize) -> DNSDistConsoleBuilder {
+        self.max_command_size = max_command_size;
+        self
+    }
+
+    /// Opens a new connection with the current settings, leaving the builder untouched so that
+    /// it can be used again
+    pub fn connect(&self) -> Result<DNSDistConsole, DNSDistConsoleError> {
+        self.clone().build()
+    }
+
+    /// Connects to the remote DNSDist console over an encrypted connection and returns a DNSDistConsole object
+    pub fn build(self) -> Result<DNSDistConsole, DNSDistConsoleError> {
+        let origin = self.clone();
+        if let Some(addrs) = &self.addresses {
+            let key = match self.key {
+                Some(key) => key,
+                None => return Err(DNSDistConsoleError::MissingParameter("key".to_string())),
+            };
+            let stream =
+                DNSDistConsole::connect_first(addrs, self.bind.as_ref(), self.connect_timeout)?;
+            return DNSDistConsoleBuilder::establish(origin, stream, key);
+        }
+
+        let host = match self.host {
+            Some(host) => host,
+            None => return Err(DNSDistConsoleError::MissingParameter("host".to_string())),
+        };
+        let port = match self.port {
+            Some(port) => port,
+            None => return Err(DNSDistConsoleError::MissingParameter("port".to_string())),
+        };
+        let key = match self.key {
+            Some(key) => key,
+            None => return Err(DNSDistConsoleError::MissingParameter("key".to_string())),
+        };
+
+        let stream = DNSDistConsole::connect(
+            &host,
+            port,
+            self.address_family,
+            self.bind.as_ref(),
+            self.connect_timeout,
+        )?;
+        DNSDistConsoleBuilder::establish(origin, stream, key)
+    }
+
+    /// Applies the settings of `origin` to a freshly connected `stream` and performs the handshake
+    fn establish(
+        origin: DNSDistConsoleBuilder,
+        mut stream: TcpStream,
+        key: ConsoleKey,
+    ) -> Result<DNSDistConsole, DNSDistConsoleError> {
+        stream.set_nodelay(origin.nodelay)?;
+        sockopt::set_keepalive(&stream, origin.keepalive)?;
+
+        let mut proxy_header_size = 0;
+        if let Some(header) = &origin.proxy_header {
+            let header = header.to_bytes()?;
+            stream.set_write_timeout(Some(origin.connect_timeout))?;
+            if let Err(e) = stream.write_all(&header) {
+                return Err(DNSDistConsoleError::from_transport(Phase::Connect, e));
+            }
+            proxy_header_size = header.len() as u64;
+        }
+
+        let handshake_timeout = origin.handshake_timeout.unwrap_or(origin.connect_timeout);
+        let mut console =
+            DNSDistConsole::handshake(Transport::Tcp(stream), key, handshake_timeout)?;
+        console.bytes_sent += proxy_header_size;
+        console.set_io_timeouts(origin.read_timeout, origin.write_timeout)?;
+        console.read_only = origin.read_only;
+        console.max_response_size = origin.max_response_size;
+        console.max_command_size = origin.max_command_size;
+        console.origin = Some(Origin::Tcp(Box::new(origin)));
+        Ok(console)
+    }
+}
+
 /// Connects to a remote DNSDist console and executes a command
 ///
 /// # Arguments
 ///
-/// * `host` - A string holding the network address of the DNSDist server (IPv4 or IPv6)
+/// * `host` - A string holding the network address (IPv4, IPv6 or scoped IPv6 like `fe80::1%eth0`) or the name of the DNSDist server
 /// * `port` - The port of the console on the remote server
-/// * `key` - An array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes holding the pre-shared key used to encrypt exchanges with the server
-/// * `command` - A string holding the command to execute
+/// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+/// * `command` - The command to execute, as a `&str` or a `String`
 pub fn execute_command(
     host: String,
     port: u16,
-    key: [u8; sodiumoxide::crypto::secretbox::KEYBYTES],
-    command: String,
+    key: impl Into<ConsoleKey>,
+    command: impl AsRef<str>,
 ) -> Result<String, DNSDistConsoleError> {
     let mut console: DNSDistConsole = DNSDistConsole::new(host, port, key)?;
+    console.execute(command)
+}
+
+/// Connects to a remote DNSDist console and executes a command like `execute_command`, trying
+/// again over a new connection up to `retries` times when a transport error occurs, waiting
+/// `backoff` before the first retry then twice as long before each of the next ones
+///
+/// Errors that will not go away by trying again, like `DNSDistConsoleError::KeyMismatch`, are
+/// returned right away, and the last error is returned once all the retries have failed. Note
+/// that a failure while receiving the response means the command might have been executed
+/// already, so commands that are not idempotent might be executed several times.
+///
+/// # Arguments
+///
+/// * `host` - A string holding the network address (IPv4, IPv6 or scoped IPv6 like `fe80::1%eth0`) or the name of the DNSDist server
+/// * `port` - The port of the console on the remote server
+/// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+/// * `command` - The command to execute, as a `&str` or a `String`
+/// * `retries` - The maximum number of retries after the first attempt
+/// * `backoff` - The time to wait before the first retry
+pub fn execute_command_with_retry(
+    host: String,
+    port: u16,
+    key: impl Into<ConsoleKey>,
+    command: impl AsRef<str>,
+    retries: u32,
+    backoff: Duration,
+) -> Result<String, DNSDistConsoleError> {
+    let key = key.into();
+    let mut delay = backoff;
+    let mut attempt = 0;
+    loop {
+        match execute_command(host.clone(), port, key.clone(), command.as_ref()) {
+            Err(e) if is_transient(&e) && attempt < retries => {
+                std::thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Connects to a remote DNSDist console and executes a command like `execute_command_with_retry`,
+/// but tries again as many times as possible until `deadline`, instead of a fixed number of
+/// times, and gives up with `DNSDistConsoleError::Timeout` once it has passed
+///
+/// Every step of every attempt, from connecting to receiving the response, is bounded by the
+/// time left before the deadline, and the wait before each retry starts at
+/// `DEADLINE_RETRY_BACKOFF` and doubles each time, never going past the deadline. Errors that
+/// will not go away by trying again, like `DNSDistConsoleError::KeyMismatch`, are returned right
+/// away. The same caveat as `execute_command_with_retry` applies to commands that are not
+/// idempotent.
+///
+/// # Arguments
+///
+/// * `host` - A string holding the network address (IPv4, IPv6 or scoped IPv6 like `fe80::1%eth0`) or the name of the DNSDist server
+/// * `port` - The port of the console on the remote server
+/// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+/// * `command` - The command to execute, as a `&str` or a `String`
+/// * `deadline` - The time by which the response has to be received
+pub fn execute_before(
+    host: String,
+    port: u16,
+    key: impl Into<ConsoleKey>,
+    command: impl AsRef<str>,
+    deadline: Instant,
+) -> Result<String, DNSDistConsoleError> {
+    let key = key.into();
+    let mut delay = DEADLINE_RETRY_BACKOFF;
+    loop {
+        match execute_command_before(&host, port, key.clone(), command.as_ref(), deadline) {
+            // whatever failed, it failed because we ran out of time
+            Err(_) if Instant::now() >= deadline => return Err(DNSDistConsoleError::Timeout),
+            Err(e) if is_transient(&e) => {
+                std::thread::sleep(delay.min(time_left(deadline)?));
+                delay = delay.saturating_mul(2);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Returns whether `error` might go away by trying again over a new connection
+///
+/// `DNSDistConsoleError::ClosedBeforeKeyConfirmed` is not, as it is how dnsdist reacts to a
+/// wrong key.
+fn is_transient(error: &DNSDistConsoleError) -> bool {
+    matches!(
+        error,
+        DNSDistConsoleError::TransportError(_, _)
+            | DNSDistConsoleError::ConnectionClosed
+            | DNSDistConsoleError::IOError(_)
+    )
+}
+
+/// Connects to several remote DNSDist consoles sharing the same key, for example the members
+/// of a cluster, and executes the same command on each of them, in parallel
+///
+/// The results are returned in the same order as `hosts`, along with the host they come from.
+///
+/// # Arguments
+///
+/// * `hosts` - The network addresses or names of the DNSDist servers, along with the port of their console
+/// * `key` - The pre-shared key used to encrypt exchanges with the servers, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+/// * `command` - The command to execute
+pub fn execute_command_on(
+    hosts: &[(String, u16)],
+    key: impl Into<ConsoleKey>,
+    command: &str,
+) -> Vec<(String, Result<String, DNSDistConsoleError>)> {
+    let key = key.into();
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = hosts
+            .iter()
+            .map(|(host, port)| {
+                let key = key.clone();
+                scope.spawn(move || execute_command(host.clone(), *port, key, command))
+            })
+            .collect();
+        hosts
+            .iter()
+            .zip(workers)
+            .map(|((host, _), worker)| (host.clone(), worker.join().unwrap()))
+            .collect()
+    })
+}
+
+/// Connects to a remote DNSDist console and executes a command, giving up with
+/// `DNSDistConsoleError::Timeout` if connecting, sending the command and receiving the response
+/// take more than `timeout` altogether
+///
+/// # Arguments
+///
+/// * `host` - A string holding the network address (IPv4, IPv6 or scoped IPv6 like `fe80::1%eth0`) or the name of the DNSDist server
+/// * `port` - The port of the console on the remote server
+/// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+/// * `command` - The command to execute, as a `&str` or a `String`
+/// * `timeout` - The maximum amount of time the whole operation can take
+pub fn execute_command_with_timeout(
+    host: String,
+    port: u16,
+    key: impl Into<ConsoleKey>,
+    command: impl AsRef<str>,
+    timeout: Duration,
+) -> Result<String, DNSDistConsoleError> {
+    let deadline = Instant::now() + timeout;
+    match execute_command_before(&host, port, key.into(), command.as_ref(), deadline) {
+        // whatever failed, it failed because we ran out of time
+        Err(_) if Instant::now() >= deadline => Err(DNSDistConsoleError::Timeout),
+        result => result,
+    }
+}
+
+/// Executes a command over a new connection opened like any other one by a
+/// `DNSDistConsoleBuilder`, the connection and the handshake each being given the time left
+/// before `deadline`, then the socket timeouts of sending and receiving being set to what is left
+fn execute_command_before(
+    host: &str,
+    port: u16,
+    key: ConsoleKey,
+    command: &str,
+    deadline: Instant,
+) -> Result<String, DNSDistConsoleError> {
+    // the same setup as any other connection, only the timeouts differ
+    let left = time_left(deadline)?;
+    let mut console = DNSDistConsoleBuilder::new()
+        .host(host.to_string())
+        .port(port)
+        .key(key)
+        .connect_timeout(left)
+        .handshake_timeout(left)
+        .build()?;
+    console.set_io_timeouts(None, Some(time_left(deadline)?))?;
     console.send(command)?;
+    console.set_io_timeouts(Some(time_left(deadline)?), None)?;
     console.receive()
 }
+
+/// Returns the time left before `deadline`, or `DNSDistConsoleError::Timeout` if it has passed
+fn time_left(deadline: Instant) -> Result<Duration, DNSDistConsoleError> {
+    match deadline.checked_duration_since(Instant::now()) {
+        Some(left) if left > Duration::from_secs(0) => Ok(left),
+        _ => Err(DNSDistConsoleError::Timeout),
+    }
+}
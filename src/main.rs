@@ -1,41 +1,487 @@
 use std::env;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
 use std::process;
 
-fn main() {
-    sodiumoxide::init().unwrap();
+use lib_rs_dnsdist_console::{
+    ConfigKey, ConsoleConfig, ConsoleKey, DNSDistConsole, DNSDistConsoleError,
+};
+
+/// The environment variable the key is read from when it is not passed on the command-line
+const KEY_ENV_VAR: &str = "DNSDIST_CONSOLE_KEY";
+
+/// The port used when none is passed on the command-line
+const DEFAULT_PORT: u16 = 5900;
+
+/// The exit code when the key cannot be decoded or does not match the one of the server
+const EXIT_KEY_ERROR: i32 = 2;
+
+/// The exit code when the server cannot be reached or the connection is lost
+const EXIT_CONNECTION_ERROR: i32 = 3;
+
+/// The exit code when a response cannot be decrypted
+const EXIT_DECRYPTION_ERROR: i32 = 4;
+
+/// Where the console key is read from
+enum KeySource {
+    Value(String),
+    /// a key already decoded, from a configuration file
+    Key(ConsoleKey),
+    File(String),
+    Env(String),
+}
+
+/// The options parsed from the command-line
+struct Options {
+    host: String,
+    port: u16,
+    key: KeySource,
+    interactive: bool,
+    json: bool,
+    command: Option<String>,
+}
+
+fn print_usage(name: &str) {
+    println!("usage: {} [OPTIONS] --host HOST [COMMAND]", name);
+    println!(
+        "       {} [--interactive] HOST [KEY] [PORT [COMMAND]]",
+        name
+    );
+    println!();
+    println!("Options:");
+    println!("  --host HOST      network address or name of the dnsdist server");
+    println!(
+        "  --port PORT      port of the console (default: {})",
+        DEFAULT_PORT
+    );
+    println!("  --key KEY        base64-encoded console key, '-' to use the environment");
+    println!("  --key-file PATH  read the base64-encoded console key from PATH");
+    println!(
+        "  --key-env VAR    read the base64-encoded console key from the VAR environment variable (default: {})",
+        KEY_ENV_VAR
+    );
+    println!("  --config PATH    read the host, port and key of the target from the PATH configuration file");
+    println!("  --target NAME    the target to use from the configuration file, if it has several");
+    println!("  --interactive    read commands from the standard input, one per line");
+    println!("  --json           print the result of each command as a JSON object");
+    println!("  -h, --help       print this help");
+    println!();
+    println!(
+        "If COMMAND is omitted or --interactive is set, commands are read from the standard input"
+    );
+    println!("If COMMAND is '-', a single command is read from the standard input");
+    println!();
+    println!("Exit codes:");
+    println!("  1  any other error");
+    println!(
+        "  {}  the key is invalid or does not match the one of the server",
+        EXIT_KEY_ERROR
+    );
+    println!(
+        "  {}  the server cannot be reached or the connection was lost",
+        EXIT_CONNECTION_ERROR
+    );
+    println!(
+        "  {}  a response cannot be decrypted",
+        EXIT_DECRYPTION_ERROR
+    );
+}
+
+/// Returns the exit code matching the category of `error`
+fn exit_code(error: &DNSDistConsoleError) -> i32 {
+    match error {
+        DNSDistConsoleError::InvalidKey(_)
+        | DNSDistConsoleError::KeyMismatch
+        | DNSDistConsoleError::ClosedBeforeKeyConfirmed => EXIT_KEY_ERROR,
+        DNSDistConsoleError::TransportError(_, _)
+        | DNSDistConsoleError::HandshakeFailed(_)
+        | DNSDistConsoleError::ConnectionClosed
+        | DNSDistConsoleError::ConnectionPoisoned
+        | DNSDistConsoleError::Timeout
+        | DNSDistConsoleError::IOError(_) => EXIT_CONNECTION_ERROR,
+        DNSDistConsoleError::DecryptionError | DNSDistConsoleError::MalformedFrame(_) => {
+            EXIT_DECRYPTION_ERROR
+        }
+        _ => 1,
+    }
+}
 
-    let mut args = env::args();
-    let name = args
-        .next()
-        .unwrap_or_else(|| "rs-dnsdist-console".to_string());
+/// Prints `error` to the standard error and exits with the code matching its category
+fn fail(error: DNSDistConsoleError) -> ! {
+    eprintln!("error: {}", error);
+    process::exit(exit_code(&error));
+}
+
+fn usage(name: &str) -> ! {
+    print_usage(name);
+    process::exit(1);
+}
+
+/// Parses the command-line arguments, either using flags or, when `--host` is not set, the
+/// historical positional form: HOST [KEY] [PORT [COMMAND]]
+fn parse_args(name: &str, args: Vec<String>) -> Options {
+    let mut host = None;
+    let mut port = None;
+    let mut key = None;
+    let mut config = None;
+    let mut target = None;
+    let mut interactive = false;
+    let mut json = false;
+    let mut positional = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            positional.extend(args.by_ref());
+            break;
+        }
+        if arg == "-h" || arg == "--help" {
+            print_usage(name);
+            process::exit(0);
+        }
+        if arg == "--interactive" {
+            interactive = true;
+            continue;
+        }
+        if arg == "--json" {
+            json = true;
+            continue;
+        }
+        if !arg.starts_with("--") {
+            positional.push(arg);
+            continue;
+        }
+
+        let (flag, value) = match arg.find('=') {
+            Some(pos) => (arg[..pos].to_string(), Some(arg[pos + 1..].to_string())),
+            None => (arg.clone(), None),
+        };
+        let mut value = || {
+            value.clone().or_else(|| args.next()).unwrap_or_else(|| {
+                eprintln!("Missing value for {}", flag);
+                process::exit(1);
+            })
+        };
+        match flag.as_str() {
+            "--host" => host = Some(value()),
+            "--port" => port = Some(parse_port(&value())),
+            "--key" => key = Some(KeySource::Value(value())),
+            "--key-file" => key = Some(KeySource::File(value())),
+            "--key-env" => key = Some(KeySource::Env(value())),
+            "--config" => config = Some(value()),
+            "--target" => target = Some(value()),
+            _ => {
+                eprintln!("Unknown option {}", flag);
+                usage(name);
+            }
+        }
+    }
+
+    match (config, target) {
+        (Some(path), target) => {
+            // settings passed on the command-line take precedence
+            let target = read_target(&path, target.as_deref());
+            host = host.or(Some(target.host));
+            port = port.or(Some(target.port));
+            key = key.or(Some(match target.key {
+                ConfigKey::Base64(key) => KeySource::Key(key),
+                ConfigKey::File(path) => KeySource::File(path.to_string_lossy().into_owned()),
+            }));
+        }
+        (None, Some(_)) => {
+            eprintln!("--target requires --config");
+            usage(name);
+        }
+        (None, None) => {}
+    }
+
+    let mut positional = positional.into_iter().peekable();
+    let host = match host {
+        Some(host) => host,
+        None => {
+            let host = positional.next().unwrap_or_else(|| usage(name));
+            match positional.peek() {
+                // HOST PORT [COMMAND], the key comes from the environment
+                Some(arg) if arg.parse::<u16>().is_ok() => {}
+                Some(_) => {
+                    let value = positional.next().unwrap_or_else(|| usage(name));
+                    key = key.or(Some(KeySource::Value(value)));
+                }
+                None => {}
+            }
+            if let Some(value) = positional.next() {
+                port = port.or_else(|| Some(parse_port(&value)));
+            }
+            host
+        }
+    };
 
-    let host = args.next().unwrap_or_else(|| {
-        println!("usage: {} HOST KEY PORT COMMAND", name);
+    let command: Vec<String> = positional.collect();
+    Options {
+        host,
+        port: port.unwrap_or(DEFAULT_PORT),
+        key: key.unwrap_or_else(|| KeySource::Env(KEY_ENV_VAR.to_string())),
+        interactive,
+        json,
+        command: if command.is_empty() {
+            None
+        } else {
+            Some(command.join(" "))
+        },
+    }
+}
+
+/// Loads the configuration file at `path` and returns the target named `name`, or the only one
+/// if no name is given
+fn read_target(path: &str, name: Option<&str>) -> ConsoleConfig {
+    let mut targets = ConsoleConfig::load_targets(path).unwrap_or_else(|error| {
+        eprintln!("Unable to load the configuration from {}: {}", path, error);
         process::exit(1);
     });
-    let key_b64 = args.next().unwrap_or_else(|| {
-        println!("usage: {} HOST KEY PORT COMMAND", name);
+    let name = match name {
+        Some(name) => name.to_string(),
+        None if targets.len() == 1 => targets.keys().next().unwrap().clone(),
+        None => {
+            eprintln!(
+                "{} holds {} targets, pick one with --target",
+                path,
+                targets.len()
+            );
+            process::exit(1);
+        }
+    };
+    targets.remove(&name).unwrap_or_else(|| {
+        eprintln!("No target named {} in {}", name, path);
         process::exit(1);
-    });
-    let mut key: [u8; sodiumoxide::crypto::secretbox::KEYBYTES] =
-        [0; sodiumoxide::crypto::secretbox::KEYBYTES];
-    base64::decode_config_slice(key_b64, base64::STANDARD, &mut key).unwrap_or_else(|error| {
-        eprintln!("Unable to decode key: {}", error.to_string());
+    })
+}
+
+fn parse_port(port: &str) -> u16 {
+    port.parse::<u16>().unwrap_or_else(|_| {
+        eprintln!("Invalid port: {}", port);
         process::exit(1);
+    })
+}
+
+/// Reads and decodes the console key from the requested source, reporting failures as JSON
+/// objects for `json_host` if set
+fn read_key(source: KeySource, json_host: Option<&str>) -> ConsoleKey {
+    let key_b64 = match source {
+        KeySource::Value(value) if value == "-" => read_key_env(KEY_ENV_VAR, json_host),
+        KeySource::Value(value) => value,
+        KeySource::Key(key) => return key,
+        KeySource::File(path) => read_key_file(&path, json_host),
+        KeySource::Env(var) => read_key_env(&var, json_host),
+    };
+    let key = key_b64.trim().parse::<ConsoleKey>();
+    wipe(key_b64);
+    key.unwrap_or_else(|error| match json_host {
+        Some(host) => fail_json(host, &error.to_string(), exit_code(&error)),
+        None => fail(error),
+    })
+}
+
+/// Reads the base64-encoded key from the file at `path`, warning if other users can read it
+fn read_key_file(path: &str, json_host: Option<&str>) -> String {
+    let key_b64 = fs::read_to_string(path).unwrap_or_else(|error| {
+        let message = format!("unable to read the key from {}: {}", path, error);
+        if let Some(host) = json_host {
+            fail_json(host, &message, EXIT_KEY_ERROR);
+        }
+        eprintln!("error: {}", message);
+        process::exit(EXIT_KEY_ERROR);
     });
-    let port = args
-        .next()
-        .unwrap_or_else(|| {
-            println!("usage: {} HOST KEY PORT COMMAND", name);
-            process::exit(1);
-        })
-        .parse::<u16>()
-        .unwrap_or(5900);
-    let command = args.next().unwrap_or_else(|| {
-        println!("usage: {} HOST KEY PORT COMMAND", name);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(metadata) = fs::metadata(path) {
+            if metadata.permissions().mode() & 0o077 != 0 {
+                eprintln!(
+                    "warning: {} can be accessed by other users, consider `chmod 600 {}`",
+                    path, path
+                );
+            }
+        }
+    }
+    key_b64
+}
+
+/// Overwrites a secret with zeros before releasing its memory
+fn wipe(secret: String) {
+    let mut bytes = secret.into_bytes();
+    sodiumoxide::utils::memzero(&mut bytes);
+}
+
+fn read_key_env(var: &str, json_host: Option<&str>) -> String {
+    env::var(var).unwrap_or_else(|_| {
+        let message = format!("No key passed and {} is not set", var);
+        if let Some(host) = json_host {
+            fail_json(host, &message, 1);
+        }
+        eprintln!("{}", message);
         process::exit(1);
-    });
+    })
+}
+
+/// Returns `value` as a JSON string, quotes included
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Prints the result of a command as a single-line JSON object
+fn print_json(host: &str, command: &str, result: &Result<String, DNSDistConsoleError>) {
+    let outcome = match result {
+        Ok(output) => format!("\"ok\":true,\"output\":{}", json_string(output)),
+        Err(error) => format!("\"ok\":false,\"error\":{}", json_string(&error.to_string())),
+    };
+    println!(
+        "{{\"host\":{},\"command\":{},{}}}",
+        json_string(host),
+        json_string(command),
+        outcome
+    );
+}
+
+/// Prints an error that prevents running any command as a single-line JSON object, like the
+/// results of commands, then exits with `code`
+fn fail_json(host: &str, message: &str, code: i32) -> ! {
+    println!(
+        "{{\"host\":{},\"ok\":false,\"error\":{}}}",
+        json_string(host),
+        json_string(message)
+    );
+    process::exit(code);
+}
+
+/// Keeps a single connection open and executes every line read from the standard input, until EOF,
+/// printing each result as a JSON object instead of the raw output if `json` is set
+fn interactive(mut console: DNSDistConsole, host: &str, json: bool) {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut line = String::new();
+    loop {
+        if !json {
+            print!("> ");
+            io::stdout().flush().unwrap();
+        }
+
+        line.clear();
+        let read = input.read_line(&mut line).unwrap_or_else(|error| {
+            eprintln!("Unable to read from the standard input: {}", error);
+            process::exit(1);
+        });
+        if read == 0 {
+            // EOF (Ctrl-D)
+            if !json {
+                println!();
+            }
+            break;
+        }
+
+        let command = line.trim_end_matches(&['\r', '\n'][..]);
+        if command.is_empty() {
+            continue;
+        }
+
+        if json {
+            let result = console.execute(command);
+            print_json(host, command, &result);
+            // like in text mode, stop once the connection cannot be used anymore
+            if let Err(error) = result {
+                if !console.is_usable() {
+                    process::exit(exit_code(&error));
+                }
+            }
+            continue;
+        }
+        match console.execute(command) {
+            Ok(content) => print!("{}", content),
+            // keep going unless the connection cannot be used anymore
+            Err(error) if console.is_usable() => eprintln!("error: {}", error),
+            Err(error) => fail(error),
+        }
+    }
+}
+
+/// Reads a whole command from the standard input, preserving newlines
+fn read_command_from_stdin() -> String {
+    let mut command = String::new();
+    io::stdin()
+        .read_to_string(&mut command)
+        .unwrap_or_else(|error| {
+            eprintln!("Unable to read from the standard input: {}", error);
+            process::exit(1);
+        });
+    command
+}
+
+fn main() {
+    sodiumoxide::init().unwrap();
+
+    let mut args: Vec<String> = env::args().collect();
+    let name = if args.is_empty() {
+        "rs-dnsdist-console".to_string()
+    } else {
+        args.remove(0)
+    };
+    let options = parse_args(&name, args);
+    let json_host = if options.json {
+        Some(options.host.clone())
+    } else {
+        None
+    };
+    let key = read_key(options.key, json_host.as_deref());
 
-    let content = lib_rs_dnsdist_console::execute_command(host, port, key, command).unwrap();
-    println!("{}", content);
+    match options.command {
+        Some(command) if !options.interactive => {
+            let command = if command == "-" {
+                read_command_from_stdin()
+            } else {
+                command
+            };
+            if options.json {
+                let result = lib_rs_dnsdist_console::execute_command(
+                    options.host.clone(),
+                    options.port,
+                    key,
+                    &command,
+                );
+                print_json(&options.host, &command, &result);
+                if let Err(error) = result {
+                    process::exit(exit_code(&error));
+                }
+                return;
+            }
+            match lib_rs_dnsdist_console::execute_command(options.host, options.port, key, command)
+            {
+                Ok(content) => println!("{}", content),
+                Err(error) => fail(error),
+            }
+        }
+        Some(_) => usage(&name),
+        None => interactive(
+            DNSDistConsole::new(options.host.clone(), options.port, key).unwrap_or_else(|error| {
+                match json_host.as_deref() {
+                    Some(host) => fail_json(host, &error.to_string(), exit_code(&error)),
+                    None => fail(error),
+                }
+            }),
+            &options.host,
+            options.json,
+        ),
+    }
 }
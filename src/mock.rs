@@ -0,0 +1,206 @@
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::protocol::{
+    decode_frame_length, encode_frame_length, increment_nonce_inplace, FRAME_LENGTH_SIZE,
+};
+use crate::{derive_session_nonces, ConsoleKey};
+
+type Handler = Box<dyn FnMut(&str) -> String + Send>;
+
+/// A fake dnsdist console listening on the loopback interface, to test code using this crate
+/// without a running dnsdist
+///
+/// The server performs the server side of the handshake on every incoming connection, then
+/// answers every command using the handler it has been created with. It stops accepting new
+/// connections when dropped.
+///
+/// ```
+/// # use lib_rs_dnsdist_console::{DNSDistConsole, MockConsoleServer};
+/// # fn main() -> Result<(), lib_rs_dnsdist_console::DNSDistConsoleError> {
+/// let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+/// let server = MockConsoleServer::start(key, |command| match command {
+///     "showVersion()" => "dnsdist 1.6.1\n".to_string(),
+///     _ => "unknown command\n".to_string(),
+/// })?;
+/// let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), key)?;
+/// assert_eq!(console.execute("showVersion()")?, "dnsdist 1.6.1\n");
+/// assert_eq!(server.commands(), vec!["showVersion()".to_string()]);
+/// # Ok(())
+/// # }
+/// ```
+pub struct MockConsoleServer {
+    addr: SocketAddr,
+    commands: Arc<Mutex<Vec<String>>>,
+    connections: Arc<AtomicUsize>,
+    stopped: Arc<AtomicBool>,
+    acceptor: Option<thread::JoinHandle<()>>,
+}
+
+impl MockConsoleServer {
+    /// Starts a server answering every command with the string returned by `handler`
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The pre-shared key used to encrypt exchanges with the clients
+    /// * `handler` - A function called with every command received, on any connection, returning the response
+    pub fn start<F>(
+        key: impl Into<ConsoleKey>,
+        handler: F,
+    ) -> Result<MockConsoleServer, std::io::Error>
+    where
+        F: FnMut(&str) -> String + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let key = key.into();
+        let handler: Arc<Mutex<Handler>> = Arc::new(Mutex::new(Box::new(handler)));
+        let commands = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(AtomicUsize::new(0));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let acceptor = {
+            let commands = commands.clone();
+            let connections = connections.clone();
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(_) => continue,
+                    };
+                    connections.fetch_add(1, Ordering::SeqCst);
+                    let key = key.clone();
+                    let handler = handler.clone();
+                    let commands = commands.clone();
+                    thread::spawn(move || {
+                        let _ = serve(stream, &key, &handler, &commands);
+                    });
+                }
+            })
+        };
+
+        Ok(MockConsoleServer {
+            addr,
+            commands,
+            connections,
+            stopped,
+            acceptor: Some(acceptor),
+        })
+    }
+
+    /// Starts a server answering every command with the command itself
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The pre-shared key used to encrypt exchanges with the clients
+    pub fn echo(key: impl Into<ConsoleKey>) -> Result<MockConsoleServer, std::io::Error> {
+        MockConsoleServer::start(key, |command| command.to_string())
+    }
+
+    /// Starts a server answering commands with `responses`, in order, then with empty responses
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The pre-shared key used to encrypt exchanges with the clients
+    /// * `responses` - The responses to send back, one per command received
+    pub fn scripted(
+        key: impl Into<ConsoleKey>,
+        responses: Vec<String>,
+    ) -> Result<MockConsoleServer, std::io::Error> {
+        let mut responses = responses.into_iter();
+        MockConsoleServer::start(key, move |_| responses.next().unwrap_or_default())
+    }
+
+    /// Returns the address the server is listening on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the port the server is listening on, on `127.0.0.1`
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Returns the commands received so far, on all connections, in the order they were received
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// Returns the number of connections accepted so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for MockConsoleServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // wake the acceptor up so that it notices it has to stop
+        let _ = TcpStream::connect(self.addr);
+        if let Some(acceptor) = self.acceptor.take() {
+            let _ = acceptor.join();
+        }
+    }
+}
+
+/// Performs the server side of the handshake, then answers commands until the client goes away
+fn serve(
+    mut stream: TcpStream,
+    key: &ConsoleKey,
+    handler: &Mutex<Handler>,
+    commands: &Mutex<Vec<String>>,
+) -> Result<(), std::io::Error> {
+    stream.set_nodelay(true)?;
+    let mut client_nonce = [0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES];
+    stream.read_exact(&mut client_nonce)?;
+    let mut our_nonce = [0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES];
+    sodiumoxide::randombytes::randombytes_into(&mut our_nonce);
+    stream.write_all(&our_nonce)?;
+
+    // the reverse of what the client does
+    let (mut writing_nonce, mut reading_nonce) = derive_session_nonces(&client_nonce, &our_nonce);
+    let secret_key = sodiumoxide::crypto::secretbox::Key(*key.as_bytes());
+
+    loop {
+        let mut len_buffer = [0_u8; FRAME_LENGTH_SIZE];
+        if stream.read_exact(&mut len_buffer).is_err() {
+            return Ok(());
+        }
+        let mut ciphertext = vec![0_u8; decode_frame_length(len_buffer) as usize];
+        stream.read_exact(&mut ciphertext)?;
+        let command = match sodiumoxide::crypto::secretbox::open(
+            &ciphertext,
+            &sodiumoxide::crypto::secretbox::Nonce(reading_nonce),
+            &secret_key,
+        ) {
+            Ok(command) => command,
+            // like dnsdist, close the connection if the client does not have the right key
+            Err(()) => return Ok(()),
+        };
+        increment_nonce_inplace(&mut reading_nonce);
+
+        let command = String::from_utf8_lossy(&command).into_owned();
+        let response = (handler.lock().unwrap())(&command);
+        commands.lock().unwrap().push(command);
+
+        let ciphertext = sodiumoxide::crypto::secretbox::seal(
+            response.as_bytes(),
+            &sodiumoxide::crypto::secretbox::Nonce(writing_nonce),
+            &secret_key,
+        );
+        increment_nonce_inplace(&mut writing_nonce);
+        let data_size: u32 = ciphertext.len().try_into().unwrap_or(u32::MAX);
+        let mut frame = Vec::with_capacity(FRAME_LENGTH_SIZE + ciphertext.len());
+        frame.extend_from_slice(&encode_frame_length(data_size));
+        frame.extend_from_slice(&ciphertext);
+        stream.write_all(&frame)?;
+    }
+}
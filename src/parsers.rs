@@ -0,0 +1,700 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+
+use thiserror::Error;
+
+/// ParseError enumerates the errors returned when parsing the output of a console command
+#[derive(Error, Debug, PartialEq)]
+pub enum ParseError {
+    #[error("no header line found")]
+    MissingHeader,
+
+    #[error("missing column `{0}` in the header")]
+    MissingColumn(String),
+
+    #[error("invalid line {0}: {1}")]
+    InvalidLine(usize, String),
+
+    #[error("missing counter `{0}`")]
+    MissingCounter(String),
+}
+
+/// A backend server, as listed by `showServers()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerEntry {
+    pub id: u64,
+    pub name: String,
+    pub address: String,
+    pub state: String,
+    pub qps: f64,
+    pub qps_limit: u64,
+    pub order: u64,
+    pub weight: u64,
+    pub queries: u64,
+    pub drops: u64,
+    pub drop_rate: f64,
+    /// The average UDP latency, in milliseconds
+    pub latency: f64,
+    /// The average TCP latency, in milliseconds, only reported by recent versions of dnsdist
+    pub tcp_latency: Option<f64>,
+    pub outstanding: u64,
+    pub pools: Vec<String>,
+}
+
+/// The columns of a table, as found in its header line
+struct Columns {
+    names: Vec<String>,
+}
+
+impl Columns {
+    fn new(header: &str) -> Columns {
+        Columns {
+            names: header
+                .split_whitespace()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+
+    fn index(&self, name: &str) -> Result<usize, ParseError> {
+        match self.names.iter().position(|column| column == name) {
+            Some(idx) => Ok(idx),
+            None => Err(ParseError::MissingColumn(name.to_string())),
+        }
+    }
+
+    fn optional_index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|column| column == name)
+    }
+}
+
+/// The fields of a line of a table, split on whitespace
+struct Fields<'a> {
+    line_number: usize,
+    fields: Vec<&'a str>,
+}
+
+impl<'a> Fields<'a> {
+    fn get(&self, idx: usize) -> Result<&'a str, ParseError> {
+        match self.fields.get(idx) {
+            Some(field) => Ok(field),
+            None => Err(ParseError::InvalidLine(
+                self.line_number,
+                format!("missing field {}", idx + 1),
+            )),
+        }
+    }
+
+    fn parse<T: std::str::FromStr>(&self, idx: usize) -> Result<T, ParseError> {
+        let field = self.get(idx)?;
+        match field.parse::<T>() {
+            Ok(value) => Ok(value),
+            Err(_) => Err(ParseError::InvalidLine(
+                self.line_number,
+                format!("invalid value `{}` for field {}", field, idx + 1),
+            )),
+        }
+    }
+
+    fn rest(&self, idx: usize) -> Vec<String> {
+        self.fields
+            .iter()
+            .skip(idx)
+            .map(|field| field.to_string())
+            .collect()
+    }
+}
+
+/// Splits the output of a console command into its header and the numbered lines that follow
+fn split_table(output: &str) -> Result<(Columns, Vec<Fields<'_>>), ParseError> {
+    let mut lines = output
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let header = match lines.next() {
+        Some((_, line)) if line.trim_start().starts_with('#') => line,
+        _ => return Err(ParseError::MissingHeader),
+    };
+    let rows = lines
+        .map(|(idx, line)| Fields {
+            line_number: idx + 1,
+            fields: line.split_whitespace().collect(),
+        })
+        .collect();
+    Ok((Columns::new(header), rows))
+}
+
+/// Parses the output of the `showServers()` console command into a list of servers
+///
+/// The columns are located using the header line, so that the additional columns of recent
+/// versions of dnsdist are handled. The `All` summary line is skipped. Since fields are
+/// separated by whitespace, server names containing spaces are not supported.
+///
+/// # Arguments
+///
+/// * `output` - The response to the `showServers()` command
+pub fn parse_show_servers(output: &str) -> Result<Vec<ServerEntry>, ParseError> {
+    let (columns, rows) = split_table(output)?;
+    let id = columns.index("#")?;
+    let name = columns.index("Name")?;
+    let address = columns.index("Address")?;
+    let state = columns.index("State")?;
+    let qps = columns.index("Qps")?;
+    let qps_limit = columns.index("Qlim")?;
+    let order = columns.index("Ord")?;
+    let weight = columns.index("Wt")?;
+    let queries = columns.index("Queries")?;
+    let drops = columns.index("Drops")?;
+    let drop_rate = columns.index("Drate")?;
+    let latency = columns.index("Lat")?;
+    let tcp_latency = columns.optional_index("TCP");
+    let outstanding = columns.index("Outstanding")?;
+    let pools = columns.index("Pools")?;
+
+    let mut servers = Vec::new();
+    for row in rows {
+        if row.get(0)? == "All" {
+            continue;
+        }
+        servers.push(ServerEntry {
+            id: row.parse(id)?,
+            name: row.get(name)?.to_string(),
+            address: row.get(address)?.to_string(),
+            state: row.get(state)?.to_string(),
+            qps: row.parse(qps)?,
+            qps_limit: row.parse(qps_limit)?,
+            order: row.parse(order)?,
+            weight: row.parse(weight)?,
+            queries: row.parse(queries)?,
+            drops: row.parse(drops)?,
+            drop_rate: row.parse(drop_rate)?,
+            latency: row.parse(latency)?,
+            tcp_latency: match tcp_latency {
+                Some(idx) => Some(row.parse(idx)?),
+                None => None,
+            },
+            outstanding: row.parse(outstanding)?,
+            pools: row.rest(pools),
+        });
+    }
+    Ok(servers)
+}
+
+/// A frontend, a socket dnsdist listens on, as listed by `showFrontends()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frontend {
+    pub id: u64,
+    pub address: String,
+    /// The protocol, like `UDP`, `TCP`, `DoT`, `DoH` or `UDP (DNSCrypt)`
+    pub protocol: String,
+    pub queries: u64,
+    /// The number of dropped queries, only reported by versions of dnsdist that have a `Drops`
+    /// column
+    pub drops: Option<u64>,
+}
+
+/// Parses the output of the `showFrontends()` console command into a list of frontends
+///
+/// The protocol of a frontend can contain spaces, like `UDP (DNSCrypt)`, so the columns
+/// following it are located from the end of the line.
+///
+/// # Arguments
+///
+/// * `output` - The response to the `showFrontends()` command
+pub fn parse_show_frontends(output: &str) -> Result<Vec<Frontend>, ParseError> {
+    let (columns, rows) = split_table(output)?;
+    let id = columns.index("#")?;
+    let address = columns.index("Address")?;
+    let protocol = columns.index("Protocol")?;
+    let queries = columns.index("Queries")?;
+    let drops = columns.optional_index("Drops");
+    // the number of columns after the protocol, which are counted from the end of the line
+    let after_protocol = columns.names.len() - protocol - 1;
+
+    let mut frontends = Vec::new();
+    for row in rows {
+        let protocol_end = match row.fields.len().checked_sub(after_protocol) {
+            Some(end) if end > protocol => end,
+            _ => {
+                return Err(ParseError::InvalidLine(
+                    row.line_number,
+                    "missing fields".to_string(),
+                ))
+            }
+        };
+        let field = |idx: usize| {
+            if idx > protocol {
+                protocol_end + (idx - protocol - 1)
+            } else {
+                idx
+            }
+        };
+        frontends.push(Frontend {
+            id: row.parse(field(id))?,
+            address: row.get(field(address))?.to_string(),
+            protocol: row.fields[protocol..protocol_end].join(" "),
+            queries: row.parse(field(queries))?,
+            drops: match drops {
+                Some(idx) => Some(row.parse(field(idx))?),
+                None => None,
+            },
+        });
+    }
+    Ok(frontends)
+}
+
+/// A server pool, as listed by `showPools()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolInfo {
+    /// The name of the pool, empty for the default pool
+    pub name: String,
+    /// The number of entries in the packet cache of the pool then its maximum number of entries,
+    /// if the pool has a packet cache
+    pub cache: Option<(u64, u64)>,
+    /// The load-balancing policy of the pool, like `leastOutstanding`
+    pub policy: String,
+    /// The servers of the pool, as printed by dnsdist: the name of the server followed by its
+    /// address, or only its address if it has no name
+    pub servers: Vec<String>,
+}
+
+/// Parses the output of the `showPools()` console command into a list of pools
+///
+/// The name and cache columns are empty for the default pool and for pools without a packet
+/// cache, so the fields are told apart by their content: the cache field is the only one made of
+/// two numbers separated by a `/`. Since fields are separated by whitespace, pool names and
+/// policy names containing spaces are not supported.
+///
+/// # Arguments
+///
+/// * `output` - The response to the `showPools()` command
+pub fn parse_show_pools(output: &str) -> Result<Vec<PoolInfo>, ParseError> {
+    let mut lines = output
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let header = match lines.next() {
+        Some((_, line)) if line.trim_start().starts_with("Name") => Columns::new(line),
+        _ => return Err(ParseError::MissingHeader),
+    };
+    for name in &["Cache", "ServerPolicy", "Servers"] {
+        header.index(name)?;
+    }
+
+    let mut pools = Vec::new();
+    for (idx, line) in lines {
+        let invalid = |reason: &str| ParseError::InvalidLine(idx + 1, reason.to_string());
+        // the default pool has no name, so its line starts with the padding of the name column
+        let (name, rest) = if line.starts_with(char::is_whitespace) {
+            ("", line)
+        } else {
+            next_field(line)
+        };
+        let (field, rest) = next_field(rest);
+        let (cache, (policy, rest)) = match parse_cache(field) {
+            Some(cache) => (Some(cache), next_field(rest)),
+            None => (None, (field, rest)),
+        };
+        if policy.is_empty() {
+            return Err(invalid("missing policy"));
+        }
+
+        let servers = rest.trim();
+        pools.push(PoolInfo {
+            name: name.to_string(),
+            cache,
+            policy: policy.to_string(),
+            servers: if servers.is_empty() {
+                Vec::new()
+            } else {
+                servers
+                    .split(',')
+                    .map(|server| server.trim().to_string())
+                    .collect()
+            },
+        });
+    }
+    Ok(pools)
+}
+
+/// Returns the first whitespace-separated field of `line` and what follows it
+fn next_field(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    match line.find(char::is_whitespace) {
+        Some(end) => (&line[..end], &line[end..]),
+        None => (line, ""),
+    }
+}
+
+/// Parses the `entries/max entries` cache field of `showPools()`
+fn parse_cache(field: &str) -> Option<(u64, u64)> {
+    let pos = field.find('/')?;
+    match (field[..pos].parse(), field[pos + 1..].parse()) {
+        (Ok(entries), Ok(max_entries)) => Some((entries, max_entries)),
+        _ => None,
+    }
+}
+
+/// Parses the output of the `dumpStats()` console command into a map of counter names to values
+///
+/// dnsdist prints the counters as name and value pairs separated by whitespace, two pairs per
+/// line.
+///
+/// # Arguments
+///
+/// * `output` - The response to the `dumpStats()` command
+pub fn parse_dump_stats(output: &str) -> Result<HashMap<String, f64>, ParseError> {
+    let mut stats = HashMap::new();
+    for counter in dump_stats_counters(output)? {
+        stats.insert(counter.name.to_string(), counter.value);
+    }
+    Ok(stats)
+}
+
+/// A counter of the output of `dumpStats()`, along with its raw value and the line it is on
+struct DumpStatsCounter<'a> {
+    line_number: usize,
+    name: &'a str,
+    raw: &'a str,
+    value: f64,
+}
+
+fn dump_stats_counters(output: &str) -> Result<Vec<DumpStatsCounter<'_>>, ParseError> {
+    let mut counters = Vec::new();
+    for (idx, line) in output.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        for pair in fields.chunks(2) {
+            if pair.len() != 2 {
+                return Err(ParseError::InvalidLine(
+                    idx + 1,
+                    format!("missing value for counter `{}`", pair[0]),
+                ));
+            }
+            let value = match pair[1].parse::<f64>() {
+                Ok(value) => value,
+                Err(_) => {
+                    return Err(ParseError::InvalidLine(
+                        idx + 1,
+                        format!("invalid value `{}` for counter `{}`", pair[1], pair[0]),
+                    ))
+                }
+            };
+            counters.push(DumpStatsCounter {
+                line_number: idx + 1,
+                name: pair[0],
+                raw: pair[1],
+                value,
+            });
+        }
+    }
+    Ok(counters)
+}
+
+/// The most commonly monitored counters reported by `dumpStats()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statistics {
+    pub queries: u64,
+    pub responses: u64,
+    pub servfail_responses: u64,
+    pub acl_drops: u64,
+    pub rule_drop: u64,
+    pub rule_nxdomain: u64,
+    pub rule_refused: u64,
+    pub self_answered: u64,
+    pub downstream_timeouts: u64,
+    pub downstream_send_errors: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    /// The average latency over the last 100 queries, in microseconds
+    pub latency_avg100: f64,
+    /// The uptime, in seconds
+    pub uptime: u64,
+}
+
+/// Parses the output of the `dumpStats()` console command into the most commonly monitored
+/// counters, see `parse_dump_stats` to get all of them
+///
+/// # Arguments
+///
+/// * `output` - The response to the `dumpStats()` command
+pub fn parse_statistics(output: &str) -> Result<Statistics, ParseError> {
+    let counters = dump_stats_counters(output)?;
+    let find = |name: &str| match counters.iter().rev().find(|counter| counter.name == name) {
+        Some(counter) => Ok(counter),
+        None => Err(ParseError::MissingCounter(name.to_string())),
+    };
+    // the counters are integers, parsing them as such catches what a conversion from the
+    // floating-point value would silently clamp or round
+    let get = |name: &str| {
+        let counter = find(name)?;
+        match counter.raw.parse::<u64>() {
+            Ok(value) => Ok(value),
+            Err(_) => Err(ParseError::InvalidLine(
+                counter.line_number,
+                format!(
+                    "invalid value `{}` for counter `{}`, expected a non-negative integer",
+                    counter.raw, name
+                ),
+            )),
+        }
+    };
+    Ok(Statistics {
+        queries: get("queries")?,
+        responses: get("responses")?,
+        servfail_responses: get("servfail-responses")?,
+        acl_drops: get("acl-drops")?,
+        rule_drop: get("rule-drop")?,
+        rule_nxdomain: get("rule-nxdomain")?,
+        rule_refused: get("rule-refused")?,
+        self_answered: get("self-answered")?,
+        downstream_timeouts: get("downstream-timeouts")?,
+        downstream_send_errors: get("downstream-send-errors")?,
+        cache_hits: get("cache-hits")?,
+        cache_misses: get("cache-misses")?,
+        latency_avg100: find("latency-avg100")?.value,
+        uptime: get("uptime")?,
+    })
+}
+
+/// A network in CIDR notation, like the entries of the console ACL
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Netmask {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl Netmask {
+    /// Creates a network from its address and the length of its prefix, returning `None` if the
+    /// prefix is longer than the address or if the address has bits set after the prefix
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Netmask> {
+        let netmask = Netmask { addr, prefix_len };
+        if prefix_len > netmask.max_prefix_len() || netmask.host_bits(&addr) != 0 {
+            return None;
+        }
+        Some(netmask)
+    }
+
+    /// Returns the address of the network
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Returns the length of the prefix of the network, in bits
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Returns whether `addr` belongs to the network, which it never does if it is not of the
+    /// same family
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+                // a /0 network contains every address, and shifting by 128 bits would overflow
+                (address_bits(addr) ^ address_bits(&self.addr))
+                    .checked_shr(self.host_len())
+                    .unwrap_or(0)
+                    == 0
+            }
+            _ => false,
+        }
+    }
+
+    fn max_prefix_len(&self) -> u8 {
+        match self.addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    fn host_len(&self) -> u32 {
+        u32::from(self.max_prefix_len() - self.prefix_len)
+    }
+
+    /// Returns the bits of `addr` after the prefix
+    fn host_bits(&self, addr: &IpAddr) -> u128 {
+        match self.host_len() {
+            128 => address_bits(addr),
+            len => address_bits(addr) & ((1_u128 << len) - 1),
+        }
+    }
+}
+
+impl fmt::Display for Netmask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+fn address_bits(addr: &IpAddr) -> u128 {
+    match addr {
+        IpAddr::V4(addr) => u128::from(u32::from(*addr)),
+        IpAddr::V6(addr) => u128::from(*addr),
+    }
+}
+
+/// Parses the output of the `showACL()` console command, one network per line, into a list of
+/// networks
+///
+/// Blank lines are skipped. An address without a prefix length is a network holding only that
+/// address, and a network with bits set after its prefix, like `192.0.2.1/24`, is rejected since
+/// dnsdist never prints one.
+///
+/// # Arguments
+///
+/// * `output` - The response to the `showACL()` command
+pub fn parse_acl(output: &str) -> Result<Vec<Netmask>, ParseError> {
+    let mut networks = Vec::new();
+    for (idx, line) in output.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid =
+            |reason: &str| ParseError::InvalidLine(idx + 1, format!("{} `{}`", reason, line));
+        let (addr, prefix_len) = match line.find('/') {
+            Some(pos) => (&line[..pos], Some(&line[pos + 1..])),
+            None => (line, None),
+        };
+        let addr = match addr.parse::<IpAddr>() {
+            Ok(addr) => addr,
+            Err(_) => return Err(invalid("invalid address in")),
+        };
+        let prefix_len = match prefix_len {
+            // only plain decimal digits, `parse` would also accept a sign
+            Some(len) if !len.is_empty() && len.bytes().all(|c| c.is_ascii_digit()) => {
+                match len.parse::<u8>() {
+                    Ok(len) => len,
+                    Err(_) => return Err(invalid("invalid prefix length in")),
+                }
+            }
+            Some(_) => return Err(invalid("invalid prefix length in")),
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        match Netmask::new(addr, prefix_len) {
+            Some(network) => networks.push(network),
+            None => return Err(invalid("invalid network")),
+        }
+    }
+    Ok(networks)
+}
+
+/// The version of a dnsdist server, as reported by `showVersion()`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ServerVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    /// The pre-release part, like `rc1` in `1.7.0-rc1`
+    pub pre_release: Option<String>,
+}
+
+impl ServerVersion {
+    /// Creates a release version
+    pub fn new(major: u64, minor: u64, patch: u64) -> ServerVersion {
+        ServerVersion {
+            major,
+            minor,
+            patch,
+            pre_release: None,
+        }
+    }
+}
+
+impl Ord for ServerVersion {
+    /// Orders versions numerically, a pre-release coming before the corresponding release
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (&self.pre_release, &other.pre_release) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(ours), Some(theirs)) => ours.cmp(theirs),
+            })
+    }
+}
+
+impl PartialOrd for ServerVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if let Some(pre_release) = &self.pre_release {
+            write!(f, "-{}", pre_release)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the output of the `showVersion()` console command, like `dnsdist 1.6.1`
+///
+/// # Arguments
+///
+/// * `output` - The response to the `showVersion()` command
+pub fn parse_version(output: &str) -> Result<ServerVersion, ParseError> {
+    let invalid = || ParseError::InvalidLine(1, format!("invalid version `{}`", output.trim()));
+    let version = match output.trim().strip_prefix("dnsdist ") {
+        Some(version) => version.trim(),
+        None => return Err(invalid()),
+    };
+    let (numbers, pre_release) = match version.find('-') {
+        Some(pos) => (&version[..pos], Some(version[pos + 1..].to_string())),
+        None => (version, None),
+    };
+
+    let mut numbers = numbers.split('.').map(|number| number.parse::<u64>());
+    let (major, minor, patch) = match (
+        numbers.next(),
+        numbers.next(),
+        numbers.next(),
+        numbers.next(),
+    ) {
+        (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => (major, minor, patch),
+        _ => return Err(invalid()),
+    };
+    Ok(ServerVersion {
+        major,
+        minor,
+        patch,
+        pre_release,
+    })
+}
+
+/// The security status of a dnsdist server, as found by its security polling
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecurityStatus {
+    /// No status has been retrieved yet, or security polling is disabled
+    Unknown,
+    /// The version is not affected by any known vulnerability
+    Ok,
+    /// An upgrade is recommended
+    UpgradeRecommended,
+    /// The version is affected by a known vulnerability and has to be upgraded
+    UpgradeRequired,
+}
+
+/// Parses the output of the `showSecurityStatus()` console command, the numerical status code
+/// set by the security polling
+///
+/// # Arguments
+///
+/// * `output` - The response to the `showSecurityStatus()` command
+pub fn parse_security_status(output: &str) -> Result<SecurityStatus, ParseError> {
+    match output.trim() {
+        "0" => Ok(SecurityStatus::Unknown),
+        "1" => Ok(SecurityStatus::Ok),
+        "2" => Ok(SecurityStatus::UpgradeRecommended),
+        "3" => Ok(SecurityStatus::UpgradeRequired),
+        status => Err(ParseError::InvalidLine(
+            1,
+            format!("invalid security status `{}`", status),
+        )),
+    }
+}
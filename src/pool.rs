@@ -0,0 +1,136 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+
+use crate::{DNSDistConsole, DNSDistConsoleBuilder, DNSDistConsoleError};
+
+struct PoolState {
+    idle: Vec<DNSDistConsole>,
+    /// the number of connections currently open, idle or handed out
+    open: usize,
+}
+
+/// A pool of connections to the same dnsdist console, so that the cost of the handshake is
+/// amortized over many commands
+///
+/// Connections are opened lazily, up to `size`, using the builder the pool has been created
+/// with. When all connections are in use, `get` waits for one to be returned. Connections
+/// that are no longer usable after an error, or that the server closed while they were idle,
+/// are dropped and replaced by new ones.
+///
+/// ```no_run
+/// # use lib_rs_dnsdist_console::{ConsolePool, DNSDistConsoleBuilder};
+/// # fn main() -> Result<(), lib_rs_dnsdist_console::DNSDistConsoleError> {
+/// # let key = [0_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+/// let builder = DNSDistConsoleBuilder::new()
+///     .host("127.0.0.1".to_string())
+///     .port(5199)
+///     .key(key);
+/// let pool = ConsolePool::new(builder, 4);
+/// let mut console = pool.get()?;
+/// println!("{}", console.execute("showVersion()")?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct ConsolePool {
+    builder: DNSDistConsoleBuilder,
+    size: usize,
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+impl ConsolePool {
+    /// Creates a new pool of at most `size` connections
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - The builder used to open new connections
+    /// * `size` - The maximum number of connections open at the same time
+    pub fn new(builder: DNSDistConsoleBuilder, size: usize) -> ConsolePool {
+        ConsolePool {
+            builder,
+            size,
+            state: Mutex::new(PoolState {
+                idle: Vec::new(),
+                open: 0,
+            }),
+            available: Condvar::new(),
+        }
+    }
+
+    /// Returns a connection from the pool, opening a new one if none is idle and the pool is not
+    /// full, or waiting for one to be returned otherwise. The connection goes back to the pool
+    /// when the returned guard is dropped.
+    pub fn get(&self) -> Result<PooledConsole<'_>, DNSDistConsoleError> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(mut console) = state.idle.pop() {
+                // the server might have closed the connection while it was idle
+                if console.is_idle() {
+                    return Ok(PooledConsole {
+                        pool: self,
+                        console: Some(console),
+                    });
+                }
+                state.open -= 1;
+                continue;
+            }
+
+            if state.open < self.size {
+                state.open += 1;
+                drop(state);
+                return match self.builder.connect() {
+                    Ok(console) => Ok(PooledConsole {
+                        pool: self,
+                        console: Some(console),
+                    }),
+                    Err(e) => {
+                        self.release(None);
+                        Err(e)
+                    }
+                };
+            }
+
+            state = self.available.wait(state).unwrap();
+        }
+    }
+
+    /// Returns the number of connections currently open, idle or in use
+    pub fn open_connections(&self) -> usize {
+        self.state.lock().unwrap().open
+    }
+
+    fn release(&self, console: Option<DNSDistConsole>) {
+        let mut state = self.state.lock().unwrap();
+        match console {
+            Some(console) if console.is_usable() => state.idle.push(console),
+            _ => state.open -= 1,
+        }
+        self.available.notify_one();
+    }
+}
+
+/// A connection borrowed from a `ConsolePool`, which is returned to the pool when dropped
+pub struct PooledConsole<'a> {
+    pool: &'a ConsolePool,
+    console: Option<DNSDistConsole>,
+}
+
+impl Deref for PooledConsole<'_> {
+    type Target = DNSDistConsole;
+
+    fn deref(&self) -> &DNSDistConsole {
+        self.console.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConsole<'_> {
+    fn deref_mut(&mut self) -> &mut DNSDistConsole {
+        self.console.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledConsole<'_> {
+    fn drop(&mut self) {
+        self.pool.release(self.console.take());
+    }
+}
@@ -0,0 +1,325 @@
+use std::convert::TryInto;
+use std::io::{ErrorKind, Read, Write};
+
+use crate::{ConsoleKey, DNSDistConsoleError, Phase, MAX_COMMAND_SIZE};
+
+/// The size of the length prefix of every frame, in bytes
+///
+/// The length of the ciphertext following it is encoded as an unsigned 32-bit integer in
+/// network byte order (big-endian), in both directions, see `encode_frame_length` and
+/// `decode_frame_length`.
+pub const FRAME_LENGTH_SIZE: usize = 4;
+
+/// Encodes the length of the ciphertext of a frame into its prefix
+pub fn encode_frame_length(length: u32) -> [u8; FRAME_LENGTH_SIZE] {
+    length.to_be_bytes()
+}
+
+/// Decodes the prefix of a frame into the length of its ciphertext, so that a custom transport
+/// knows how many bytes to wait for before calling `ConsoleProtocol::decode_response`
+pub fn decode_frame_length(prefix: [u8; FRAME_LENGTH_SIZE]) -> u32 {
+    u32::from_be_bytes(prefix)
+}
+
+/// Computes the nonces of a session from the nonces exchanged during the handshake, the same
+/// way dnsdist does, and returns the nonce used by the client to decrypt responses then the one
+/// used to encrypt commands
+///
+/// The reading nonce is made of the first half of the client nonce followed by the second half
+/// of the server nonce, and the writing nonce of the first half of the server nonce followed by
+/// the second half of the client nonce. The server uses them the other way around.
+///
+/// # Arguments
+///
+/// * `client_nonce` - The nonce sent by the client during the handshake
+/// * `server_nonce` - The nonce sent by the server during the handshake
+pub fn derive_session_nonces(
+    client_nonce: &[u8; sodiumoxide::crypto::secretbox::NONCEBYTES],
+    server_nonce: &[u8; sodiumoxide::crypto::secretbox::NONCEBYTES],
+) -> (
+    [u8; sodiumoxide::crypto::secretbox::NONCEBYTES],
+    [u8; sodiumoxide::crypto::secretbox::NONCEBYTES],
+) {
+    let half = sodiumoxide::crypto::secretbox::NONCEBYTES / 2;
+    let mut reading_nonce = *client_nonce;
+    reading_nonce[half..].copy_from_slice(&server_nonce[half..]);
+    let mut writing_nonce = *server_nonce;
+    writing_nonce[half..].copy_from_slice(&client_nonce[half..]);
+    (reading_nonce, writing_nonce)
+}
+
+/// The client side of the console protocol, without any I/O besides the handshake: it turns
+/// commands into frames and frames into responses, keeping track of the nonces
+///
+/// `DNSDistConsole` delegates to it, and it can be used on its own to drive the protocol over a
+/// different transport, or to test against in-memory buffers.
+///
+/// Each side has a writing nonce and a reading nonce. A nonce is always used first, to encrypt a
+/// command or to decrypt a response, then incremented, exactly like dnsdist does: the first
+/// command is encrypted with the writing nonce derived during the handshake, and the first
+/// response decrypted with the derived reading nonce. The reading nonce is incremented for every
+/// frame received, even one that fails to decrypt, since the server has moved on as well.
+///
+/// ```
+/// # use lib_rs_dnsdist_console::{derive_session_nonces, ConsoleProtocol};
+/// # fn main() -> Result<(), lib_rs_dnsdist_console::DNSDistConsoleError> {
+/// let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+/// let (reading_nonce, writing_nonce) = derive_session_nonces(&[2; 24], &[3; 24]);
+/// let mut client = ConsoleProtocol::new(key, reading_nonce, writing_nonce);
+/// // the server uses the nonces the other way around
+/// let mut server = ConsoleProtocol::new(key, writing_nonce, reading_nonce);
+///
+/// let frame = client.encode_command("showVersion()")?;
+/// assert_eq!(server.decode_response(&frame[4..])?, "showVersion()");
+/// # Ok(())
+/// # }
+/// ```
+// Do not derive Debug: it would print the secret key and the nonces.
+pub struct ConsoleProtocol {
+    writing_nonce: sodiumoxide::crypto::secretbox::Nonce,
+    reading_nonce: sodiumoxide::crypto::secretbox::Nonce,
+    secret_key: sodiumoxide::crypto::secretbox::xsalsa20poly1305::Key,
+    /// whether a response has been successfully decrypted, proving that we have the right key
+    key_confirmed: bool,
+    /// whether commands and responses are exchanged without encryption
+    plaintext: bool,
+}
+
+impl ConsoleProtocol {
+    /// Creates the state of a session whose handshake has already been completed
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+    /// * `reading_nonce` - The nonce that will be used to decrypt the next response from the server
+    /// * `writing_nonce` - The nonce that will be used to encrypt the next command sent to the server
+    pub fn new(
+        key: impl Into<ConsoleKey>,
+        reading_nonce: [u8; sodiumoxide::crypto::secretbox::NONCEBYTES],
+        writing_nonce: [u8; sodiumoxide::crypto::secretbox::NONCEBYTES],
+    ) -> ConsoleProtocol {
+        ConsoleProtocol {
+            writing_nonce: sodiumoxide::crypto::secretbox::Nonce(writing_nonce),
+            reading_nonce: sodiumoxide::crypto::secretbox::Nonce(reading_nonce),
+            secret_key: sodiumoxide::crypto::secretbox::xsalsa20poly1305::Key(
+                *key.into().as_bytes(),
+            ),
+            key_confirmed: false,
+            plaintext: false,
+        }
+    }
+
+    /// Creates the state of a session that does not use encryption
+    #[cfg(feature = "insecure-plaintext")]
+    pub(crate) fn plaintext() -> ConsoleProtocol {
+        let mut protocol = ConsoleProtocol::new(
+            [0_u8; sodiumoxide::crypto::secretbox::KEYBYTES],
+            [0; sodiumoxide::crypto::secretbox::NONCEBYTES],
+            [0; sodiumoxide::crypto::secretbox::NONCEBYTES],
+        );
+        protocol.plaintext = true;
+        protocol
+    }
+
+    /// Returns a new random nonce for the client side of a handshake
+    ///
+    /// Together with `from_handshake`, this allows performing the handshake over a transport that
+    /// is neither `Read` nor `Write`, like a message bus: send this nonce to the server, wait for
+    /// its own `NONCEBYTES` bytes, then pass both to `from_handshake`.
+    pub fn client_nonce() -> [u8; sodiumoxide::crypto::secretbox::NONCEBYTES] {
+        let mut nonce = [0; sodiumoxide::crypto::secretbox::NONCEBYTES];
+        sodiumoxide::randombytes::randombytes_into(&mut nonce);
+        nonce
+    }
+
+    /// Creates the state of a session from the nonces exchanged during its handshake
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+    /// * `client_nonce` - The nonce sent to the server, usually from `client_nonce`
+    /// * `server_nonce` - The nonce received from the server
+    pub fn from_handshake(
+        key: impl Into<ConsoleKey>,
+        client_nonce: &[u8; sodiumoxide::crypto::secretbox::NONCEBYTES],
+        server_nonce: &[u8; sodiumoxide::crypto::secretbox::NONCEBYTES],
+    ) -> Result<ConsoleProtocol, DNSDistConsoleError> {
+        let key = key.into();
+        key.validate()?;
+        let (reading_nonce, writing_nonce) = derive_session_nonces(client_nonce, server_nonce);
+        Ok(ConsoleProtocol::new(key, reading_nonce, writing_nonce))
+    }
+
+    /// Performs the nonce exchange over `stream`, on which nothing has been exchanged yet
+    ///
+    /// The handshake is exactly: the client sends `NONCEBYTES` random bytes, the server answers
+    /// with its own `NONCEBYTES` random bytes, and nothing else. dnsdist does not send any banner
+    /// or version, so everything received after the server's nonce belongs to the first response
+    /// frame. The server's nonce may arrive in several segments, which are read until it is
+    /// complete. If the server closes the connection before that, the error tells whether it sent
+    /// nothing at all, usually because the console ACL rejected the client, or only part of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - The stream connected to the console
+    /// * `key` - The pre-shared key used to encrypt exchanges with the server, as a `ConsoleKey` or an array of `sodiumoxide::crypto::secretbox::KEYBYTES` bytes
+    pub fn handshake<S: Read + Write>(
+        stream: &mut S,
+        key: impl Into<ConsoleKey>,
+    ) -> Result<ConsoleProtocol, DNSDistConsoleError> {
+        let key = key.into();
+        key.validate()?;
+
+        let our_nonce = ConsoleProtocol::client_nonce();
+        if let Err(e) = stream.write_all(&our_nonce) {
+            return Err(DNSDistConsoleError::from_transport(Phase::Handshake, e));
+        }
+
+        let mut remote_nonce: [u8; sodiumoxide::crypto::secretbox::NONCEBYTES] =
+            [0; sodiumoxide::crypto::secretbox::NONCEBYTES];
+        // like `read_exact`, but keeping track of how much has been received to tell a server
+        // rejecting the connection from one going away in the middle of the handshake
+        let mut received = 0;
+        while received < remote_nonce.len() {
+            let closed = match stream.read(&mut remote_nonce[received..]) {
+                Ok(0) => true,
+                Ok(read) => {
+                    received += read;
+                    false
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => false,
+                Err(e) if e.kind() == ErrorKind::ConnectionReset => true,
+                Err(e) => return Err(DNSDistConsoleError::from_transport(Phase::Handshake, e)),
+            };
+            if closed && received == 0 {
+                return Err(DNSDistConsoleError::HandshakeFailed(
+                    "the server closed the connection before sending its nonce, check that the console is enabled and that the ACL allows this client".to_string(),
+                ));
+            }
+            if closed {
+                return Err(DNSDistConsoleError::HandshakeFailed(format!(
+                    "the server closed the connection after sending only {} of the {} bytes of its nonce, check that the port is the one of a dnsdist console and that the server did not crash",
+                    received,
+                    remote_nonce.len()
+                )));
+            }
+        }
+
+        ConsoleProtocol::from_handshake(key, &our_nonce, &remote_nonce)
+    }
+
+    /// Returns the nonce that will be used to decrypt the next response from the server
+    pub fn reading_nonce(&self) -> [u8; sodiumoxide::crypto::secretbox::NONCEBYTES] {
+        self.reading_nonce.0
+    }
+
+    /// Returns the nonce that will be used to encrypt the next command sent to the server
+    pub fn writing_nonce(&self) -> [u8; sodiumoxide::crypto::secretbox::NONCEBYTES] {
+        self.writing_nonce.0
+    }
+
+    /// Returns the raw bytes of the key
+    pub(crate) fn key_bytes(&self) -> [u8; sodiumoxide::crypto::secretbox::KEYBYTES] {
+        self.secret_key.0
+    }
+
+    /// Encrypts `command` with the current writing nonce and returns the whole frame to send to
+    /// the server, length included, then increments the writing nonce
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command to encode
+    pub fn encode_command(&mut self, command: &str) -> Result<Vec<u8>, DNSDistConsoleError> {
+        if command.len() > MAX_COMMAND_SIZE {
+            return Err(DNSDistConsoleError::CommandTooLarge(command.len()));
+        }
+
+        let ciphertext = if self.plaintext {
+            command.as_bytes().to_vec()
+        } else {
+            sodiumoxide::crypto::secretbox::seal(
+                command.as_bytes(),
+                &self.writing_nonce,
+                &self.secret_key,
+            )
+        };
+
+        let data_size: u32 = match ciphertext.len().try_into() {
+            Ok(data_size) => data_size,
+            Err(_) => return Err(DNSDistConsoleError::CommandTooLarge(command.len())),
+        };
+
+        let mut frame = Vec::with_capacity(FRAME_LENGTH_SIZE + ciphertext.len());
+        frame.extend_from_slice(&encode_frame_length(data_size));
+        frame.extend_from_slice(&ciphertext);
+        increment_nonce_inplace(&mut self.writing_nonce.0);
+        Ok(frame)
+    }
+
+    /// Decrypts the content of a frame received from the server, without its length, with the
+    /// current reading nonce and returns the response, then increments the reading nonce
+    ///
+    /// # Arguments
+    ///
+    /// * `ciphertext` - The content of the frame, after the `FRAME_LENGTH_SIZE` bytes of the length
+    pub fn decode_response(&mut self, ciphertext: &[u8]) -> Result<String, DNSDistConsoleError> {
+        let mut frame = ciphertext.to_vec();
+        let start = self.open_in_place(&mut frame)?;
+        frame.drain(..start);
+        Ok(String::from_utf8(frame)?)
+    }
+
+    /// Returns whether a response has been decrypted, proving that the key matches the one of the
+    /// server, which is always the case on a plaintext connection
+    pub(crate) fn key_confirmed(&self) -> bool {
+        self.key_confirmed || self.plaintext
+    }
+
+    /// Decrypts the content of a frame in place, returning the offset of the response in it: the
+    /// response follows the first `sodiumoxide::crypto::secretbox::MACBYTES` bytes, where the
+    /// authentication tag was, or starts right away on a plaintext connection
+    pub(crate) fn open_in_place(&mut self, frame: &mut [u8]) -> Result<usize, DNSDistConsoleError> {
+        if self.plaintext {
+            return Ok(0);
+        }
+
+        if frame.len() < sodiumoxide::crypto::secretbox::MACBYTES {
+            // every encrypted frame holds at least the authentication tag, even for an empty
+            // response. The frame has been read entirely, so the connection is still in sync.
+            increment_nonce_inplace(&mut self.reading_nonce.0);
+            return Err(DNSDistConsoleError::MalformedFrame(frame.len()));
+        }
+
+        let (tag, ciphertext) = frame.split_at_mut(sodiumoxide::crypto::secretbox::MACBYTES);
+        let tag = sodiumoxide::crypto::secretbox::Tag::from_slice(tag).unwrap();
+        let opened = sodiumoxide::crypto::secretbox::open_detached(
+            ciphertext,
+            &tag,
+            &self.reading_nonce,
+            &self.secret_key,
+        );
+        increment_nonce_inplace(&mut self.reading_nonce.0);
+
+        match opened {
+            Ok(()) => {
+                self.key_confirmed = true;
+                Ok(sodiumoxide::crypto::secretbox::MACBYTES)
+            }
+            // the nonces are exchanged in the clear, so a wrong key is only noticed now
+            Err(()) if !self.key_confirmed => Err(DNSDistConsoleError::KeyMismatch),
+            Err(()) => Err(DNSDistConsoleError::DecryptionError),
+        }
+    }
+}
+
+/// Increments a nonce the same way dnsdist does: the first 4 bytes are treated as a big-endian
+/// counter which wraps around, without carrying into the remaining bytes. This is not what
+/// libsodium's `sodium_increment` does, but doing anything else would break the session
+/// once the counter rolls over.
+pub(crate) fn increment_nonce_inplace(nonce: &mut [u8]) {
+    assert!(nonce.len() >= 4, "invalid nonce size");
+    let mut counter: [u8; 4] = [0; 4];
+    counter.copy_from_slice(&nonce[..4]);
+    let value = u32::from_be_bytes(counter).wrapping_add(1);
+    nonce[..4].copy_from_slice(&value.to_be_bytes());
+}
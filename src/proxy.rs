@@ -0,0 +1,98 @@
+use std::convert::TryInto;
+use std::net::SocketAddr;
+
+use crate::DNSDistConsoleError;
+
+/// The signature starting every version 2 header
+const V2_SIGNATURE: [u8; 12] = [
+    0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a,
+];
+
+/// A PROXY protocol header, sent right after connecting and before the handshake so that a proxy
+/// in front of the console can convey the address of the real client, see
+/// `DNSDistConsoleBuilder::proxy_header`
+///
+/// The source and destination addresses have to be of the same family.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProxyHeader {
+    /// A version 1, human-readable, header
+    V1 {
+        /// The address of the real client
+        source: SocketAddr,
+        /// The address the client connected to
+        destination: SocketAddr,
+    },
+    /// A version 2, binary, header
+    V2 {
+        /// The address of the real client
+        source: SocketAddr,
+        /// The address the client connected to
+        destination: SocketAddr,
+    },
+}
+
+impl ProxyHeader {
+    /// Returns the header as sent on the wire
+    pub fn to_bytes(&self) -> Result<Vec<u8>, DNSDistConsoleError> {
+        match self {
+            ProxyHeader::V1 {
+                source,
+                destination,
+            } => {
+                let family = match (source, destination) {
+                    (SocketAddr::V4(_), SocketAddr::V4(_)) => "TCP4",
+                    (SocketAddr::V6(_), SocketAddr::V6(_)) => "TCP6",
+                    _ => return Err(mixed_families()),
+                };
+                Ok(format!(
+                    "PROXY {} {} {} {} {}\r\n",
+                    family,
+                    source.ip(),
+                    destination.ip(),
+                    source.port(),
+                    destination.port()
+                )
+                .into_bytes())
+            }
+            ProxyHeader::V2 {
+                source,
+                destination,
+            } => {
+                let mut addresses = Vec::with_capacity(36);
+                let family = match (source, destination) {
+                    (SocketAddr::V4(source), SocketAddr::V4(destination)) => {
+                        addresses.extend_from_slice(&source.ip().octets());
+                        addresses.extend_from_slice(&destination.ip().octets());
+                        0x11
+                    }
+                    (SocketAddr::V6(source), SocketAddr::V6(destination)) => {
+                        addresses.extend_from_slice(&source.ip().octets());
+                        addresses.extend_from_slice(&destination.ip().octets());
+                        0x21
+                    }
+                    _ => return Err(mixed_families()),
+                };
+                addresses.extend_from_slice(&source.port().to_be_bytes());
+                addresses.extend_from_slice(&destination.port().to_be_bytes());
+
+                // the addresses are at most 36 bytes long, so this cannot fail
+                let length: u16 = addresses.len().try_into().unwrap();
+                let mut header = Vec::with_capacity(V2_SIGNATURE.len() + 4 + addresses.len());
+                header.extend_from_slice(&V2_SIGNATURE);
+                // version 2, PROXY command
+                header.push(0x21);
+                header.push(family);
+                header.extend_from_slice(&length.to_be_bytes());
+                header.extend_from_slice(&addresses);
+                Ok(header)
+            }
+        }
+    }
+}
+
+fn mixed_families() -> DNSDistConsoleError {
+    DNSDistConsoleError::InvalidArgument(
+        "the source and destination of a PROXY protocol header have to be of the same family"
+            .to_string(),
+    )
+}
@@ -0,0 +1,145 @@
+use crate::ParseError;
+
+/// A typed introspection query, which builds the Lua expression sent to the console and parses
+/// its response, see `DNSDistConsole::query`
+///
+/// The expressions evaluate to a single string holding one value per line, which dnsdist sends
+/// back as the response, so that parsing does not depend on the layout of the `show*()` tables.
+pub trait Query {
+    /// The type of the result of the query
+    type Output;
+
+    /// Returns the Lua expression to send to the console
+    fn expression(&self) -> String;
+
+    /// Parses the response to the expression
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - The response to the expression returned by `expression`
+    fn parse(&self, output: &str) -> Result<Self::Output, ParseError>;
+}
+
+/// The state of a backend server, as returned by `ServerStatusQuery`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerStatus {
+    pub name: String,
+    /// Whether the server is currently considered up
+    pub up: bool,
+    /// The number of queries sent to the server that are still waiting for a response
+    pub outstanding: u64,
+    pub weight: u64,
+    pub order: u64,
+}
+
+/// Queries the name and state of the server whose number is `id`, like `getServer(id)`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerStatusQuery {
+    id: u64,
+}
+
+impl ServerStatusQuery {
+    /// Creates a query for the server at index `id` of `showServers()`
+    pub fn new(id: u64) -> ServerStatusQuery {
+        ServerStatusQuery { id }
+    }
+}
+
+impl Query for ServerStatusQuery {
+    type Output = ServerStatus;
+
+    fn expression(&self) -> String {
+        format!(
+            "(function() local s = getServer({}) return table.concat({{s:getName(), tostring(s:isUp()), s:getOutstanding(), s.weight, s.order}}, \"\\n\") end)()",
+            self.id
+        )
+    }
+
+    fn parse(&self, output: &str) -> Result<ServerStatus, ParseError> {
+        let lines: Vec<&str> = output.lines().collect();
+        if lines.len() != 5 {
+            return Err(ParseError::InvalidLine(
+                1,
+                format!("expected 5 lines, got {}", lines.len()),
+            ));
+        }
+        let up = match lines[1] {
+            "true" => true,
+            "false" => false,
+            other => {
+                return Err(ParseError::InvalidLine(
+                    2,
+                    format!("invalid state `{}`", other),
+                ))
+            }
+        };
+        Ok(ServerStatus {
+            name: lines[0].to_string(),
+            up,
+            outstanding: parse_number(&lines, 2)?,
+            weight: parse_number(&lines, 3)?,
+            order: parse_number(&lines, 4)?,
+        })
+    }
+}
+
+/// Queries the servers of a pool, like `getPoolServers(pool)`, returning the name and address of
+/// each server as printed by dnsdist, like `ns1 (192.0.2.1:53)`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolServersQuery {
+    pool: String,
+}
+
+impl PoolServersQuery {
+    /// Creates a query for the pool named `pool`, the default pool having an empty name
+    pub fn new(pool: impl Into<String>) -> PoolServersQuery {
+        PoolServersQuery { pool: pool.into() }
+    }
+}
+
+impl Query for PoolServersQuery {
+    type Output = Vec<String>;
+
+    fn expression(&self) -> String {
+        format!(
+            "(function() local names = {{}} for _, s in ipairs(getPoolServers({})) do table.insert(names, s:getNameWithAddr()) end return table.concat(names, \"\\n\") end)()",
+            lua_string(&self.pool)
+        )
+    }
+
+    fn parse(&self, output: &str) -> Result<Vec<String>, ParseError> {
+        Ok(output
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| line.to_string())
+            .collect())
+    }
+}
+
+fn parse_number(lines: &[&str], idx: usize) -> Result<u64, ParseError> {
+    match lines[idx].parse() {
+        Ok(value) => Ok(value),
+        Err(_) => Err(ParseError::InvalidLine(
+            idx + 1,
+            format!("invalid value `{}`", lines[idx]),
+        )),
+    }
+}
+
+/// Returns `value` as a Lua string literal, quotes included, escaping everything that could end
+/// the literal early
+fn lua_string(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            // decimal escapes, which Lua reads as the byte with that value
+            c if c.is_ascii_control() => literal.push_str(&format!("\\{:03}", c as u32)),
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
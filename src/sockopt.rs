@@ -0,0 +1,239 @@
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+/// Enables TCP keepalive on `stream`, sending the first probe after `interval` of inactivity
+/// then every `interval` until the peer answers or the kernel gives up, or disables it if
+/// `interval` is `None`
+#[cfg(unix)]
+pub(crate) fn set_keepalive(stream: &TcpStream, interval: Option<Duration>) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = stream.as_raw_fd();
+    let interval = match interval {
+        Some(interval) => interval,
+        None => return set_option(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 0),
+    };
+    set_option(fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1)?;
+
+    // the kernel only accepts whole seconds, and at least one
+    let seconds = interval.as_secs().clamp(1, libc::c_int::MAX as u64) as libc::c_int;
+    set_keepalive_interval(fd, seconds)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_keepalive_interval(fd: libc::c_int, seconds: libc::c_int) -> std::io::Result<()> {
+    set_option(fd, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, seconds)?;
+    set_option(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, seconds)
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+fn set_keepalive_interval(fd: libc::c_int, seconds: libc::c_int) -> std::io::Result<()> {
+    set_option(fd, libc::IPPROTO_TCP, libc::TCP_KEEPALIVE, seconds)?;
+    set_option(fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, seconds)
+}
+
+/// Other platforms only get the system-wide keepalive settings
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios"
+    ))
+))]
+fn set_keepalive_interval(_fd: libc::c_int, _seconds: libc::c_int) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Keepalive is enabled by default, so on other platforms the setting is ignored rather than
+/// making every connection fail
+#[cfg(not(unix))]
+pub(crate) fn set_keepalive(
+    _stream: &TcpStream,
+    _interval: Option<Duration>,
+) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_option(
+    fd: libc::c_int,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> std::io::Result<()> {
+    // setsockopt only reads size_of::<c_int>() bytes from the value we pass
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Connects to `remote` from the local address `local`, waiting at most `timeout` for the
+/// connection to be established
+#[cfg(unix)]
+pub(crate) fn connect_from(
+    local: &SocketAddr,
+    remote: &SocketAddr,
+    timeout: Duration,
+) -> std::io::Result<TcpStream> {
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    let family = match remote {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    // the descriptor is owned by the TcpStream right away, so that it is closed on error
+    let fd = unsafe { libc::socket(family, libc::SOCK_STREAM, 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let stream = unsafe { TcpStream::from_raw_fd(fd) };
+    set_flag(fd, libc::F_GETFD, libc::F_SETFD, libc::FD_CLOEXEC, true)?;
+
+    let (addr, len) = to_sockaddr(local);
+    // bind only reads `len` bytes from the address we pass
+    if unsafe { libc::bind(fd, &addr as *const _ as *const libc::sockaddr, len) } != 0 {
+        let err = std::io::Error::last_os_error();
+        return Err(std::io::Error::new(
+            err.kind(),
+            format!("unable to bind to {}: {}", local, err),
+        ));
+    }
+
+    set_flag(fd, libc::F_GETFL, libc::F_SETFL, libc::O_NONBLOCK, true)?;
+    let (addr, len) = to_sockaddr(remote);
+    if unsafe { libc::connect(fd, &addr as *const _ as *const libc::sockaddr, len) } != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EINPROGRESS) {
+            return Err(err);
+        }
+        wait_until_connected(stream.as_raw_fd(), timeout)?;
+    }
+    set_flag(fd, libc::F_GETFL, libc::F_SETFL, libc::O_NONBLOCK, false)?;
+    Ok(stream)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn connect_from(
+    _local: &SocketAddr,
+    _remote: &SocketAddr,
+    _timeout: Duration,
+) -> std::io::Result<TcpStream> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "binding to a local address is not supported on this platform",
+    ))
+}
+
+/// Waits for a non-blocking connection attempt to complete
+#[cfg(unix)]
+fn wait_until_connected(fd: libc::c_int, timeout: Duration) -> std::io::Result<()> {
+    let timeout_ms = timeout.as_millis().clamp(1, libc::c_int::MAX as u128) as libc::c_int;
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLOUT,
+        revents: 0,
+    };
+    loop {
+        // poll only accesses the single pollfd we pass
+        let ret = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+        if ret > 0 {
+            break;
+        }
+        if ret == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "connection timed out",
+            ));
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+
+    let mut error: libc::c_int = 0;
+    let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // getsockopt writes at most `len` bytes into `error`
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_ERROR,
+            &mut error as *mut libc::c_int as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if error != 0 {
+        return Err(std::io::Error::from_raw_os_error(error));
+    }
+    Ok(())
+}
+
+/// Sets or clears `flag` in the flags read with `get` and written with `set` by `fcntl`
+#[cfg(unix)]
+fn set_flag(
+    fd: libc::c_int,
+    get: libc::c_int,
+    set: libc::c_int,
+    flag: libc::c_int,
+    enabled: bool,
+) -> std::io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, get) };
+    if flags < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let flags = if enabled { flags | flag } else { flags & !flag };
+    if unsafe { libc::fcntl(fd, set, flags) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn to_sockaddr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    // sockaddr_storage is plain old data, large enough for any address family
+    let mut storage: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let sin = &mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in;
+            unsafe {
+                (*sin).sin_family = libc::AF_INET as libc::sa_family_t;
+                (*sin).sin_port = addr.port().to_be();
+                (*sin).sin_addr = libc::in_addr {
+                    s_addr: u32::from_ne_bytes(addr.ip().octets()),
+                };
+            }
+            std::mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(addr) => {
+            let sin6 = &mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in6;
+            unsafe {
+                (*sin6).sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                (*sin6).sin6_port = addr.port().to_be();
+                (*sin6).sin6_flowinfo = addr.flowinfo();
+                (*sin6).sin6_addr = libc::in6_addr {
+                    s6_addr: addr.ip().octets(),
+                };
+                (*sin6).sin6_scope_id = addr.scope_id();
+            }
+            std::mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+    (storage, len as libc::socklen_t)
+}
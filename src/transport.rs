@@ -0,0 +1,110 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+/// The stream a console connection is established over
+pub(crate) enum Transport {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Transport {
+    pub(crate) fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Transport::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    pub(crate) fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        match self {
+            Transport::Tcp(stream) => stream.set_write_timeout(timeout),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.set_write_timeout(timeout),
+        }
+    }
+
+    /// Returns the address of the peer, which only exists for TCP connections
+    pub(crate) fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        match self {
+            Transport::Tcp(stream) => stream.peer_addr(),
+            #[cfg(unix)]
+            Transport::Unix(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "the console is connected over a unix domain socket",
+            )),
+        }
+    }
+
+    /// Returns a printable form of the address of the peer, if known
+    pub(crate) fn peer_description(&self) -> Option<String> {
+        match self {
+            Transport::Tcp(stream) => stream.peer_addr().ok().map(|addr| addr.to_string()),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream
+                .peer_addr()
+                .ok()
+                .and_then(|addr| addr.as_pathname().map(|path| path.display().to_string())),
+        }
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        match self {
+            Transport::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+
+    /// Returns whether the peer has neither closed the connection nor sent anything since the last
+    /// read, without blocking. A byte that has been received is consumed, but the connection is
+    /// out of sync anyway since nothing was expected.
+    pub(crate) fn is_idle(&mut self) -> bool {
+        if self.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let mut buffer = [0_u8; 1];
+        let idle = matches!(self.read(&mut buffer), Err(e) if e.kind() == ErrorKind::WouldBlock);
+        self.set_nonblocking(false).is_ok() && idle
+    }
+
+    pub(crate) fn shutdown(&self) -> std::io::Result<()> {
+        match self {
+            Transport::Tcp(stream) => stream.shutdown(Shutdown::Both),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.shutdown(Shutdown::Both),
+        }
+    }
+}
+
+impl Read for Transport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Transport::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Transport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Transport::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Transport::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Transport::Unix(stream) => stream.flush(),
+        }
+    }
+}
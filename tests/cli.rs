@@ -0,0 +1,129 @@
+use std::process::Command;
+
+use lib_rs_dnsdist_console::MockConsoleServer;
+
+const KEY_B64: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+fn console_command(port: u16) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rs-dnsdist-console"));
+    command.args([
+        "--host",
+        "127.0.0.1",
+        "--port",
+        &port.to_string(),
+        "--key",
+        KEY_B64,
+    ]);
+    command
+}
+
+#[test]
+fn execute() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo([1_u8; sodiumoxide::crypto::secretbox::KEYBYTES]).unwrap();
+    let output = console_command(server.port())
+        .arg("showVersion()")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "showVersion()\n");
+}
+
+#[test]
+fn wrong_key_exit_code() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo([2_u8; sodiumoxide::crypto::secretbox::KEYBYTES]).unwrap();
+    for json in &[false, true] {
+        let mut command = console_command(server.port());
+        if *json {
+            command.arg("--json");
+        }
+        let output = command.arg("showVersion()").output().unwrap();
+        assert_eq!(output.status.code(), Some(2));
+    }
+    assert!(server.commands().is_empty());
+}
+
+#[test]
+fn interactive_json_stops_when_unusable() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo([2_u8; sodiumoxide::crypto::secretbox::KEYBYTES]).unwrap();
+    let mut child = console_command(server.port())
+        .args(["--interactive", "--json"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"showVersion()\nshowServers()\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    // the second command is not even attempted
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 1);
+}
+
+/// Checks that `stdout` holds a single JSON error object for 127.0.0.1
+fn assert_json_error(stdout: &[u8]) {
+    let stdout = String::from_utf8_lossy(stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "{}", stdout);
+    assert!(
+        lines[0].starts_with("{\"host\":\"127.0.0.1\",\"ok\":false,\"error\":\""),
+        "{}",
+        stdout
+    );
+    assert!(lines[0].ends_with("\"}"), "{}", stdout);
+}
+
+#[test]
+fn json_setup_errors() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo([1_u8; sodiumoxide::crypto::secretbox::KEYBYTES]).unwrap();
+
+    // a key that cannot be decoded
+    let output = console_command(server.port())
+        .args(["--json", "--key", "not a key!", "showVersion()"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert_json_error(&output.stdout);
+
+    // no key at all
+    let output = console_command(server.port())
+        .args([
+            "--json",
+            "--key-env",
+            "RS_DNSDIST_CONSOLE_TEST_UNSET",
+            "showVersion()",
+        ])
+        .env_remove("RS_DNSDIST_CONSOLE_TEST_UNSET")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_json_error(&output.stdout);
+
+    // nothing listening, in interactive mode
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let output = console_command(port)
+        .args(["--json", "--interactive"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert_json_error(&output.stdout);
+    assert!(server.commands().is_empty());
+}
@@ -0,0 +1,85 @@
+use lib_rs_dnsdist_console::{
+    ConfigKey, ConsoleConfig, DNSDistConsole, DNSDistConsoleError, MockConsoleServer,
+};
+
+const KEY: [u8; sodiumoxide::crypto::secretbox::KEYBYTES] =
+    [1; sodiumoxide::crypto::secretbox::KEYBYTES];
+
+const KEY_B64: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+#[test]
+fn parse_targets() {
+    let targets = ConsoleConfig::parse_targets(
+        r#"
+# the edge servers
+[edge1]
+host = "192.0.2.1"
+port = 5199 # the default one
+key = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE="
+
+[ edge2 ]
+host = 'edge2.example.net'
+port = 5_900
+key_file = "/etc/dnsdist/console \"edge\".key"
+"#,
+    )
+    .unwrap();
+    assert_eq!(targets.len(), 2);
+
+    let edge1 = &targets["edge1"];
+    assert_eq!(edge1.host, "192.0.2.1");
+    assert_eq!(edge1.port, 5199);
+    assert_eq!(edge1.console_key().unwrap().as_bytes(), &KEY);
+
+    let edge2 = &targets["edge2"];
+    assert_eq!(edge2.host, "edge2.example.net");
+    assert_eq!(edge2.port, 5900);
+    match &edge2.key {
+        ConfigKey::File(path) => {
+            assert_eq!(path.to_str().unwrap(), "/etc/dnsdist/console \"edge\".key")
+        }
+        _ => panic!("expected a key file"),
+    }
+}
+
+#[test]
+fn invalid_configs() {
+    for content in &[
+        "host = \"192.0.2.1\"",
+        "[edge1]\nhost = \"192.0.2.1\"\nport = 5199",
+        "[edge1]\nhost = \"192.0.2.1\nport = 5199\nkey = \"a\"",
+        "[edge1]\nhost = 192.0.2.1\nport = 5199\nkey = \"a\"",
+        "[edge1]\nhost = \"192.0.2.1\"\nport = 100000\nkey = \"a\"",
+        "[edge1]\nhost = \"192.0.2.1\"\nport = 5199\nkey = \"a\"\nuser = \"admin\"",
+        "[edge1]\nhost = \"a\"\nport = 1\nkey = \"a\"\n[edge1]\nhost = \"a\"\nport = 1\nkey = \"a\"",
+        "[edge1",
+        "[edge1]\nhost = \"192.0.2.1\"\nport = 5199\nkey = \"AQEBAQ==\"",
+    ] {
+        // only the last one has an invalid key, the others are rejected for another reason
+        let content = content.replace("key = \"a\"", &format!("key = \"{}\"", KEY_B64));
+        match ConsoleConfig::parse_targets(&content) {
+            Err(DNSDistConsoleError::ConfigError(_)) => {}
+            _ => panic!("expected a configuration error for {}", content),
+        }
+    }
+}
+
+#[test]
+fn from_config() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let key_path =
+        std::env::temp_dir().join(format!("rs-dnsdist-console-{}.key", std::process::id()));
+    std::fs::write(&key_path, "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=\n").unwrap();
+
+    let content = format!(
+        "[local]\nhost = \"127.0.0.1\"\nport = {}\nkey_file = \"{}\"\n",
+        server.port(),
+        key_path.display()
+    );
+    let targets = ConsoleConfig::parse_targets(&content).unwrap();
+    let mut console = DNSDistConsole::from_config(&targets["local"]).unwrap();
+    assert_eq!(console.execute("showVersion()").unwrap(), "showVersion()");
+    std::fs::remove_file(&key_path).unwrap();
+}
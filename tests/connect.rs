@@ -0,0 +1,339 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lib_rs_dnsdist_console::{DNSDistConsole, DNSDistConsoleBuilder, DNSDistConsoleError, Phase};
+
+#[test]
+fn connect_by_name() {
+    sodiumoxide::init().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut client_nonce = [0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES];
+        stream.read_exact(&mut client_nonce).unwrap();
+        stream
+            .write_all(&[0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES])
+            .unwrap();
+    });
+
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    DNSDistConsole::new("localhost".to_string(), port, key).unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn connect_timeout() {
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    let timeout = Duration::from_millis(500);
+    let start = Instant::now();
+    // 10.255.255.1 is not routed, so the connection attempt should time out
+    let result = DNSDistConsole::with_timeout("10.255.255.1".to_string(), 5199, key, timeout);
+    assert!(result.is_err());
+    assert!(start.elapsed() < timeout + Duration::from_secs(1));
+}
+
+#[test]
+fn handshake_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        // accept the connection but never send our nonce, until the client gives up
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0_u8; 64];
+        while stream.read(&mut buffer).unwrap_or(0) > 0 {}
+    });
+
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    let timeout = Duration::from_millis(500);
+    let start = Instant::now();
+    let result = DNSDistConsole::with_timeout("127.0.0.1".to_string(), port, key, timeout);
+    match result {
+        Err(DNSDistConsoleError::TransportError(Phase::Handshake, e)) => {
+            assert_eq!(e.kind(), ErrorKind::TimedOut)
+        }
+        _ => panic!("expected a transport error"),
+    }
+    assert!(start.elapsed() < timeout + Duration::from_secs(1));
+    server.join().unwrap();
+}
+
+#[test]
+fn separate_handshake_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut buffer = [0_u8; 64];
+        while stream.read(&mut buffer).unwrap_or(0) > 0 {}
+    });
+
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    let timeout = Duration::from_millis(300);
+    let start = Instant::now();
+    let result = DNSDistConsoleBuilder::new()
+        .host("127.0.0.1".to_string())
+        .port(port)
+        .key(key)
+        .connect_timeout(Duration::from_secs(30))
+        .handshake_timeout(timeout)
+        .build();
+    match result {
+        Err(DNSDistConsoleError::TransportError(Phase::Handshake, e)) => {
+            assert_eq!(e.kind(), ErrorKind::TimedOut)
+        }
+        _ => panic!("expected a transport error"),
+    }
+    let elapsed = start.elapsed();
+    assert!(elapsed >= timeout);
+    assert!(elapsed < timeout + Duration::from_secs(1));
+    server.join().unwrap();
+}
+
+#[test]
+fn handshake_closed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        drop(stream);
+    });
+
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    match DNSDistConsole::new("127.0.0.1".to_string(), port, key) {
+        Err(DNSDistConsoleError::HandshakeFailed(_)) => {}
+        _ => panic!("expected a handshake failure"),
+    }
+    server.join().unwrap();
+}
+
+#[test]
+fn builder_missing_parameters() {
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    match DNSDistConsoleBuilder::new().port(5199).key(key).build() {
+        Err(DNSDistConsoleError::MissingParameter(name)) => assert_eq!(name, "host"),
+        _ => panic!("expected a missing parameter error"),
+    }
+    match DNSDistConsoleBuilder::new()
+        .host("127.0.0.1".to_string())
+        .key(key)
+        .build()
+    {
+        Err(DNSDistConsoleError::MissingParameter(name)) => assert_eq!(name, "port"),
+        _ => panic!("expected a missing parameter error"),
+    }
+    match DNSDistConsoleBuilder::new()
+        .host("127.0.0.1".to_string())
+        .port(5199)
+        .build()
+    {
+        Err(DNSDistConsoleError::MissingParameter(name)) => assert_eq!(name, "key"),
+        _ => panic!("expected a missing parameter error"),
+    }
+}
+
+#[test]
+fn invalid_arguments() {
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    match DNSDistConsole::new(String::new(), 5199, key) {
+        Err(DNSDistConsoleError::InvalidArgument(_)) => {}
+        _ => panic!("expected an invalid argument error"),
+    }
+    match DNSDistConsole::new("127.0.0.1".to_string(), 0, key) {
+        Err(DNSDistConsoleError::InvalidArgument(_)) => {}
+        _ => panic!("expected an invalid argument error"),
+    }
+    match lib_rs_dnsdist_console::execute_command_with_timeout(
+        String::new(),
+        5199,
+        key,
+        "showVersion()",
+        Duration::from_secs(1),
+    ) {
+        Err(DNSDistConsoleError::InvalidArgument(_)) => {}
+        _ => panic!("expected an invalid argument error"),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn connect_unix() {
+    use std::os::unix::net::UnixListener;
+
+    sodiumoxide::init().unwrap();
+
+    let path = std::env::temp_dir().join(format!("rs-dnsdist-console-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut client_nonce = [0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES];
+        stream.read_exact(&mut client_nonce).unwrap();
+        stream
+            .write_all(&[0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES])
+            .unwrap();
+    });
+
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    let console = DNSDistConsole::connect_unix(&path, key).unwrap();
+    // there is no TCP stream to hand over
+    assert!(matches!(
+        console.into_stream(),
+        Err(DNSDistConsoleError::InvalidArgument(_))
+    ));
+    server.join().unwrap();
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn connect_scoped_ipv6() {
+    sodiumoxide::init().unwrap();
+
+    let listener = match TcpListener::bind("[::1]:0") {
+        Ok(listener) => listener,
+        // no IPv6 support
+        Err(_) => return,
+    };
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut client_nonce = [0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES];
+            stream.read_exact(&mut client_nonce).unwrap();
+            stream
+                .write_all(&[0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES])
+                .unwrap();
+        }
+    });
+
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    DNSDistConsole::new("::1%1".to_string(), port, key).unwrap();
+    DNSDistConsole::new("::1%lo".to_string(), port, key).unwrap();
+    server.join().unwrap();
+
+    match DNSDistConsole::new("fe80::1%nonexistent0".to_string(), port, key) {
+        Err(DNSDistConsoleError::TransportError(Phase::Connect, e)) => {
+            assert_eq!(e.kind(), ErrorKind::NotFound)
+        }
+        _ => panic!("expected an unknown interface error"),
+    }
+}
+
+#[test]
+fn builder_keepalive() {
+    sodiumoxide::init().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut client_nonce = [0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES];
+            stream.read_exact(&mut client_nonce).unwrap();
+            stream
+                .write_all(&[0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES])
+                .unwrap();
+        }
+    });
+
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    let builder = DNSDistConsoleBuilder::new()
+        .host("127.0.0.1".to_string())
+        .port(port)
+        .key(key);
+    builder
+        .clone()
+        .keepalive(Some(Duration::from_secs(10)))
+        .build()
+        .unwrap();
+    builder.keepalive(None).build().unwrap();
+    server.join().unwrap();
+}
+
+#[test]
+fn execute_command_timeout() {
+    sodiumoxide::init().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        // complete the handshake but never answer the command, until the client gives up
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut client_nonce = [0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES];
+        stream.read_exact(&mut client_nonce).unwrap();
+        stream
+            .write_all(&[0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES])
+            .unwrap();
+        let mut buffer = [0_u8; 64];
+        while stream.read(&mut buffer).unwrap_or(0) > 0 {}
+    });
+
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    let timeout = Duration::from_millis(500);
+    let start = Instant::now();
+    match lib_rs_dnsdist_console::execute_command_with_timeout(
+        "127.0.0.1".to_string(),
+        port,
+        key,
+        "showVersion()",
+        timeout,
+    ) {
+        Err(DNSDistConsoleError::Timeout) => {}
+        _ => panic!("expected a timeout"),
+    }
+    assert!(start.elapsed() < timeout + Duration::from_secs(1));
+    server.join().unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn builder_bind() {
+    sodiumoxide::init().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, peer) = listener.accept().unwrap();
+        let mut client_nonce = [0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES];
+        stream.read_exact(&mut client_nonce).unwrap();
+        stream
+            .write_all(&[0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES])
+            .unwrap();
+        peer
+    });
+
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    // the whole 127.0.0.0/8 range is local on Linux
+    DNSDistConsoleBuilder::new()
+        .host("127.0.0.1".to_string())
+        .port(port)
+        .key(key)
+        .bind("127.0.0.2:0".parse().unwrap())
+        .build()
+        .unwrap();
+    let peer = server.join().unwrap();
+    assert_eq!(peer.ip().to_string(), "127.0.0.2");
+}
+
+#[cfg(unix)]
+#[test]
+fn builder_bind_not_local() {
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    // 192.0.2.0/24 is reserved for documentation, so it is not one of our addresses
+    match DNSDistConsoleBuilder::new()
+        .host("127.0.0.1".to_string())
+        .port(5199)
+        .key(key)
+        .bind("192.0.2.1:0".parse().unwrap())
+        .build()
+    {
+        Err(DNSDistConsoleError::TransportError(Phase::Connect, e)) => {
+            assert!(e.to_string().contains("bind"))
+        }
+        _ => panic!("expected a bind error"),
+    }
+}
@@ -0,0 +1,437 @@
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+use lib_rs_dnsdist_console::{DNSDistConsole, DNSDistConsoleError, Phase};
+use sodiumoxide::crypto::secretbox;
+
+const KEY: [u8; secretbox::KEYBYTES] = [1; secretbox::KEYBYTES];
+
+fn connected_console() -> (DNSDistConsole, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    let console = DNSDistConsole::from_parts(
+        client,
+        KEY,
+        [0; secretbox::NONCEBYTES],
+        [0; secretbox::NONCEBYTES],
+    );
+    (console, server)
+}
+
+#[test]
+fn response_too_large() {
+    let (mut console, mut server) = connected_console();
+    server.write_all(&u32::MAX.to_be_bytes()).unwrap();
+    match console.receive() {
+        Err(DNSDistConsoleError::ResponseTooLarge(size)) => assert_eq!(size, u32::MAX as usize),
+        _ => panic!("expected a response too large error"),
+    }
+}
+
+#[test]
+fn custom_max_response_size() {
+    let (mut console, mut server) = connected_console();
+    console.set_max_response_size(1024);
+    server.write_all(&1025_u32.to_be_bytes()).unwrap();
+    match console.receive() {
+        Err(DNSDistConsoleError::ResponseTooLarge(size)) => assert_eq!(size, 1025),
+        _ => panic!("expected a response too large error"),
+    }
+}
+
+#[test]
+fn shutdown_on_drop() {
+    let (console, mut server) = connected_console();
+    drop(console);
+    let mut buffer = [0_u8; 16];
+    assert_eq!(server.read(&mut buffer).unwrap(), 0);
+}
+
+#[test]
+fn poisoned_after_partial_frame() {
+    let (mut console, mut server) = connected_console();
+    assert!(console.is_usable());
+    // only send half of the length, then close the connection
+    server.write_all(&[0, 0]).unwrap();
+    drop(server);
+    assert!(console.receive().is_err());
+    assert!(!console.is_usable());
+    match console.send("showVersion()") {
+        Err(DNSDistConsoleError::ConnectionPoisoned) => {}
+        _ => panic!("expected a poisoned connection error"),
+    }
+    match console.receive() {
+        Err(DNSDistConsoleError::ConnectionPoisoned) => {}
+        _ => panic!("expected a poisoned connection error"),
+    }
+}
+
+fn write_response(server: &mut TcpStream, response: &[u8]) {
+    let ciphertext = secretbox::seal(
+        response,
+        &secretbox::Nonce([0; secretbox::NONCEBYTES]),
+        &secretbox::Key(KEY),
+    );
+    server
+        .write_all(&(ciphertext.len() as u32).to_be_bytes())
+        .unwrap();
+    server.write_all(&ciphertext).unwrap();
+}
+
+#[test]
+fn binary_response() {
+    let (mut console, mut server) = connected_console();
+    write_response(&mut server, &[0xff, 0xfe, 0x00]);
+    assert_eq!(console.receive_bytes().unwrap(), vec![0xff, 0xfe, 0x00]);
+}
+
+#[test]
+fn invalid_utf8_response() {
+    let (mut console, mut server) = connected_console();
+    write_response(&mut server, &[0xff, 0xfe, 0x00]);
+    match console.receive() {
+        Err(DNSDistConsoleError::Utf8Error(_)) => {}
+        _ => panic!("expected an UTF-8 error"),
+    }
+}
+
+#[test]
+fn whole_frame_written() {
+    let (mut console, mut server) = connected_console();
+    console.send("showVersion()").unwrap();
+    drop(console);
+
+    let mut frame = Vec::new();
+    server.read_to_end(&mut frame).unwrap();
+    assert_eq!(frame.len(), 4 + "showVersion()".len() + secretbox::MACBYTES);
+    assert_eq!(
+        u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize,
+        frame.len() - 4
+    );
+}
+
+#[test]
+fn poisoned_after_failed_send() {
+    let (mut console, server) = connected_console();
+    drop(server);
+    // the first writes might still succeed until the reset from the peer is received
+    let command = "a".repeat(1024 * 1024);
+    let mut failed = false;
+    for _ in 0..10 {
+        if console.send(command.clone()).is_err() {
+            failed = true;
+            break;
+        }
+    }
+    assert!(failed);
+    assert!(!console.is_usable());
+    match console.send("showVersion()") {
+        Err(DNSDistConsoleError::ConnectionPoisoned) => {}
+        _ => panic!("expected a poisoned connection error"),
+    }
+}
+
+#[test]
+fn key_mismatch() {
+    let (mut console, mut server) = connected_console();
+    let ciphertext = secretbox::seal(
+        b"dnsdist 1.6.1",
+        &secretbox::Nonce([0; secretbox::NONCEBYTES]),
+        &secretbox::Key([2; secretbox::KEYBYTES]),
+    );
+    server
+        .write_all(&(ciphertext.len() as u32).to_be_bytes())
+        .unwrap();
+    server.write_all(&ciphertext).unwrap();
+    match console.receive() {
+        Err(DNSDistConsoleError::KeyMismatch) => {}
+        _ => panic!("expected a key mismatch error"),
+    }
+}
+
+#[test]
+fn closed_before_key_confirmed() {
+    // what dnsdist does when it cannot decrypt a command
+    let (mut console, server) = connected_console();
+    drop(server);
+    match console.receive() {
+        Err(DNSDistConsoleError::ClosedBeforeKeyConfirmed) => {}
+        _ => panic!("expected a closed before key confirmed error"),
+    }
+}
+
+#[test]
+fn decryption_error_after_first_response() {
+    let (mut console, mut server) = connected_console();
+    write_response(&mut server, b"dnsdist 1.6.1");
+    assert_eq!(console.receive().unwrap(), "dnsdist 1.6.1");
+    // encrypted with the nonce of the first response, so it cannot be decrypted
+    write_response(&mut server, b"dnsdist 1.6.1");
+    match console.receive() {
+        Err(DNSDistConsoleError::DecryptionError) => {}
+        _ => panic!("expected a decryption error"),
+    }
+}
+
+#[test]
+fn receive_error_kind() {
+    let (mut console, mut server) = connected_console();
+    write_response(&mut server, b"dnsdist 1.6.1");
+    assert_eq!(console.receive().unwrap(), "dnsdist 1.6.1");
+    server.write_all(&[0, 0]).unwrap();
+    drop(server);
+    match console.receive() {
+        Err(DNSDistConsoleError::TransportError(Phase::Receive, e)) => {
+            assert_eq!(e.kind(), ErrorKind::UnexpectedEof)
+        }
+        _ => panic!("expected a transport error"),
+    }
+}
+
+#[test]
+fn receive_timeout_kind() {
+    let (mut console, _server) = connected_console();
+    console
+        .set_io_timeouts(Some(Duration::from_millis(50)), None)
+        .unwrap();
+    match console.receive() {
+        Err(DNSDistConsoleError::TransportError(Phase::Receive, e)) => {
+            assert_eq!(e.kind(), ErrorKind::TimedOut)
+        }
+        _ => panic!("expected a timeout"),
+    }
+}
+
+#[test]
+fn reconnect_from_parts() {
+    let (mut console, _server) = connected_console();
+    match console.reconnect() {
+        Err(DNSDistConsoleError::MissingParameter(_)) => {}
+        _ => panic!("expected a missing parameter error"),
+    }
+}
+
+#[test]
+fn receive_to_writer() {
+    let (mut console, mut server) = connected_console();
+    let response: Vec<u8> = (0..200_000_u32).map(|idx| idx as u8).collect();
+    let writer = std::thread::spawn(move || write_response(&mut server, &response));
+    let mut output = Vec::new();
+    assert_eq!(console.receive_to_writer(&mut output).unwrap(), 200_000);
+    writer.join().unwrap();
+    assert_eq!(output.len(), 200_000);
+    assert!(output
+        .iter()
+        .enumerate()
+        .all(|(idx, byte)| *byte == idx as u8));
+}
+
+#[test]
+fn frame_shorter_than_mac() {
+    let (mut console, mut server) = connected_console();
+    server.write_all(&4_u32.to_be_bytes()).unwrap();
+    server.write_all(&[0, 1, 2, 3]).unwrap();
+    match console.receive_bytes() {
+        Err(e @ DNSDistConsoleError::MalformedFrame(4)) => {
+            assert!(e.to_string().contains("key"))
+        }
+        _ => panic!("expected a malformed frame error"),
+    }
+    assert!(console.is_usable());
+}
+
+#[test]
+fn empty_frame() {
+    let (mut console, mut server) = connected_console();
+    server.write_all(&0_u32.to_be_bytes()).unwrap();
+    match console.receive_bytes() {
+        Err(DNSDistConsoleError::MalformedFrame(0)) => {}
+        _ => panic!("expected a malformed frame error"),
+    }
+
+    // the empty frame consumed a nonce, the next frame can still be decrypted
+    let mut nonce = [0; secretbox::NONCEBYTES];
+    nonce[3] = 1;
+    let ciphertext = secretbox::seal(
+        b"dnsdist 1.6.1",
+        &secretbox::Nonce(nonce),
+        &secretbox::Key(KEY),
+    );
+    server
+        .write_all(&(ciphertext.len() as u32).to_be_bytes())
+        .unwrap();
+    server.write_all(&ciphertext).unwrap();
+    assert_eq!(console.receive().unwrap(), "dnsdist 1.6.1");
+}
+
+#[test]
+fn debug_redacts_secrets() {
+    let (console, server) = connected_console();
+    let debug = format!("{:?}", console);
+    assert!(debug.contains(&server.local_addr().unwrap().to_string()));
+    assert!(debug.contains("usable: true"));
+    // neither the key, made of ones, nor the nonces, made of zeros, are printed
+    assert!(!debug.contains("1, 1"));
+    assert!(!debug.contains("0, 0"));
+}
+
+#[test]
+fn bytes_counters() {
+    let (mut console, mut server) = connected_console();
+    assert_eq!(console.bytes_sent(), 0);
+    assert_eq!(console.bytes_received(), 0);
+
+    console.send("showVersion()").unwrap();
+    assert_eq!(
+        console.bytes_sent(),
+        (4 + "showVersion()".len() + secretbox::MACBYTES) as u64
+    );
+
+    write_response(&mut server, b"dnsdist 1.6.1");
+    console.receive().unwrap();
+    assert_eq!(
+        console.bytes_received(),
+        (4 + "dnsdist 1.6.1".len() + secretbox::MACBYTES) as u64
+    );
+}
+
+#[test]
+fn connection_closed_on_send() {
+    let (mut console, server) = connected_console();
+    drop(server);
+    // the first writes might still succeed until the reset from the peer is received
+    let command = "a".repeat(1024 * 1024);
+    let mut result = Ok(());
+    for _ in 0..10 {
+        result = console.send(command.clone());
+        if result.is_err() {
+            break;
+        }
+    }
+    match result {
+        Err(DNSDistConsoleError::ConnectionClosed) => {}
+        _ => panic!("expected a connection closed error"),
+    }
+}
+
+#[test]
+fn receive_within() {
+    let (mut console, mut server) = connected_console();
+    console
+        .set_io_timeouts(Some(Duration::from_secs(60)), None)
+        .unwrap();
+    write_response(&mut server, b"dnsdist 1.6.1");
+    assert_eq!(
+        console.receive_within(Duration::from_secs(1)).unwrap(),
+        "dnsdist 1.6.1"
+    );
+
+    match console.receive_within(Duration::from_millis(50)) {
+        Err(DNSDistConsoleError::Timeout) => {}
+        _ => panic!("expected a timeout"),
+    }
+    assert!(!console.is_usable());
+}
+
+#[test]
+fn custom_max_command_size() {
+    let (mut console, mut server) = connected_console();
+    console.set_max_command_size(16 + secretbox::MACBYTES);
+    match console.send("a".repeat(17)) {
+        Err(DNSDistConsoleError::CommandTooLarge(size)) => assert_eq!(size, 17),
+        _ => panic!("expected a command too large error"),
+    }
+    assert!(console.is_usable());
+
+    // nothing has been written, so the next frame is the first one
+    console.send("a".repeat(16)).unwrap();
+    drop(console);
+    let mut frame = Vec::new();
+    server.read_to_end(&mut frame).unwrap();
+    assert_eq!(frame.len(), 4 + 16 + secretbox::MACBYTES);
+}
+
+#[test]
+fn responses_until_closed() {
+    let (mut console, mut server) = connected_console();
+    for (idx, response) in ["first", "second", "third"].iter().enumerate() {
+        let mut nonce = [0; secretbox::NONCEBYTES];
+        nonce[3] = idx as u8;
+        let ciphertext = secretbox::seal(
+            response.as_bytes(),
+            &secretbox::Nonce(nonce),
+            &secretbox::Key(KEY),
+        );
+        server
+            .write_all(&(ciphertext.len() as u32).to_be_bytes())
+            .unwrap();
+        server.write_all(&ciphertext).unwrap();
+    }
+    drop(server);
+
+    let responses: Vec<String> = console.responses().map(|r| r.unwrap()).collect();
+    assert_eq!(responses, vec!["first", "second", "third"]);
+}
+
+#[test]
+fn responses_until_timeout() {
+    let (mut console, mut server) = connected_console();
+    console
+        .set_io_timeouts(Some(Duration::from_millis(50)), None)
+        .unwrap();
+    write_response(&mut server, b"dnsdist 1.6.1");
+    let mut responses = console.responses();
+    assert_eq!(responses.next().unwrap().unwrap(), "dnsdist 1.6.1");
+    assert!(responses.next().is_none());
+    assert!(responses.next().is_none());
+}
+
+#[test]
+fn responses_timeout_in_frame() {
+    let (mut console, mut server) = connected_console();
+    console
+        .set_io_timeouts(Some(Duration::from_millis(50)), None)
+        .unwrap();
+    write_response(&mut server, b"dnsdist 1.6.1");
+    // only half of the length of the next frame
+    server.write_all(&[0, 0]).unwrap();
+    let mut responses = console.responses();
+    assert_eq!(responses.next().unwrap().unwrap(), "dnsdist 1.6.1");
+    match responses.next() {
+        Some(Err(DNSDistConsoleError::TransportError(Phase::Receive, e))) => {
+            assert_eq!(e.kind(), ErrorKind::TimedOut)
+        }
+        _ => panic!("expected a timeout"),
+    }
+    assert!(responses.next().is_none());
+    assert!(!console.is_usable());
+}
+
+#[test]
+fn responses_error() {
+    let (mut console, mut server) = connected_console();
+    server.write_all(&0_u32.to_be_bytes()).unwrap();
+    drop(server);
+    let responses: Vec<_> = console.responses().collect();
+    assert_eq!(responses.len(), 1);
+    assert!(matches!(
+        responses[0],
+        Err(DNSDistConsoleError::MalformedFrame(0))
+    ));
+}
+
+#[test]
+fn response_length_in_network_byte_order() {
+    let (mut console, mut server) = connected_console();
+    let ciphertext = secretbox::seal(
+        &[b'a'; 300],
+        &secretbox::Nonce([0; secretbox::NONCEBYTES]),
+        &secretbox::Key(KEY),
+    );
+    assert_eq!(ciphertext.len(), 0x013c);
+    server.write_all(&[0x00, 0x00, 0x01, 0x3c]).unwrap();
+    server.write_all(&ciphertext).unwrap();
+    assert_eq!(console.receive().unwrap(), "a".repeat(300));
+}
@@ -0,0 +1,86 @@
+use lib_rs_dnsdist_console::{decode_key, ConsoleKey, DNSDistConsole, DNSDistConsoleError};
+
+#[test]
+fn decode_valid_key() {
+    let key = decode_key("AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=").unwrap();
+    assert_eq!(key, [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES]);
+}
+
+#[test]
+fn decode_invalid_keys() {
+    // too short
+    match decode_key("AQEBAQEBAQEBAQEBAQEBAQ==") {
+        Err(DNSDistConsoleError::InvalidKey(_)) => {}
+        _ => panic!("expected an invalid key error"),
+    }
+    // too long
+    match decode_key("AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQ==") {
+        Err(DNSDistConsoleError::InvalidKey(_)) => {}
+        _ => panic!("expected an invalid key error"),
+    }
+    // not base64
+    match DNSDistConsole::from_base64_key("127.0.0.1".to_string(), 5199, "not a key!") {
+        Err(DNSDistConsoleError::InvalidKey(_)) => {}
+        _ => panic!("expected an invalid key error"),
+    }
+}
+
+#[test]
+fn truncated_key() {
+    // a key pasted without its last characters decodes to fewer bytes instead of failing to decode
+    let key_b64 = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+    match decode_key(&key_b64[..40]) {
+        Err(DNSDistConsoleError::InvalidKey(message)) => {
+            assert_eq!(message, "expected 32 bytes, got 30")
+        }
+        _ => panic!("expected an invalid key error"),
+    }
+}
+
+#[test]
+fn console_key() {
+    let key: ConsoleKey = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE="
+        .parse()
+        .unwrap();
+    assert_eq!(
+        key,
+        ConsoleKey::from([1_u8; sodiumoxide::crypto::secretbox::KEYBYTES])
+    );
+    assert_ne!(
+        key,
+        ConsoleKey::from([2_u8; sodiumoxide::crypto::secretbox::KEYBYTES])
+    );
+    assert_eq!(format!("{:?}", key), "ConsoleKey(***)");
+    assert!("AQEBAQEBAQEBAQEBAQEBAQ==".parse::<ConsoleKey>().is_err());
+}
+
+#[test]
+fn reject_zero_key() {
+    match decode_key("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=") {
+        Err(DNSDistConsoleError::InvalidKey(_)) => {}
+        _ => panic!("expected an invalid key error"),
+    }
+    // rejected before even trying to connect
+    let key = [0_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    match DNSDistConsole::new("127.0.0.1".to_string(), port, key) {
+        Err(DNSDistConsoleError::InvalidKey(_)) => {}
+        _ => panic!("expected an invalid key error"),
+    }
+}
+
+#[test]
+fn decode_key_alphabets() {
+    let expected: Vec<u8> = [0xfb_u8, 0xff].iter().cycle().take(32).cloned().collect();
+    for encoded in &[
+        "+//7//v/+//7//v/+//7//v/+//7//v/+//7//v/+/8=",
+        "+//7//v/+//7//v/+//7//v/+//7//v/+//7//v/+/8",
+        "-__7__v_-__7__v_-__7__v_-__7__v_-__7__v_-_8=",
+        "-__7__v_-__7__v_-__7__v_-__7__v_-__7__v_-_8",
+    ] {
+        assert_eq!(decode_key(encoded).unwrap().to_vec(), expected);
+    }
+    // mixing the two alphabets is not valid
+    assert!(decode_key("+__7__v_-__7__v_-__7__v_-__7__v_-__7__v_-_8=").is_err());
+}
@@ -0,0 +1,594 @@
+use lib_rs_dnsdist_console::{
+    AddressFamily, ConsolePool, ConsoleTemplate, DNSDistConsole, DNSDistConsoleBuilder,
+    DNSDistConsoleError, Direction, MockConsoleServer, SecurityStatus, ServerVersion,
+};
+
+use std::sync::{Arc, Mutex};
+
+const KEY: [u8; sodiumoxide::crypto::secretbox::KEYBYTES] =
+    [1; sodiumoxide::crypto::secretbox::KEYBYTES];
+
+#[test]
+fn echo() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    for idx in 0..3 {
+        assert_eq!(
+            console.execute(format!("command {}", idx)).unwrap(),
+            format!("command {}", idx)
+        );
+    }
+    assert_eq!(
+        server.commands(),
+        vec!["command 0", "command 1", "command 2"]
+    );
+}
+
+#[test]
+fn scripted() {
+    sodiumoxide::init().unwrap();
+
+    let server =
+        MockConsoleServer::scripted(KEY, vec!["dnsdist 1.6.1\n".to_string(), "OK\n".to_string()])
+            .unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    assert_eq!(console.execute("showVersion()").unwrap(), "dnsdist 1.6.1\n");
+    assert_eq!(console.execute("setVerbose(true)").unwrap(), "OK\n");
+    assert_eq!(console.execute("anything()").unwrap(), "");
+}
+
+#[test]
+fn several_connections() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let pool = ConsolePool::new(
+        DNSDistConsoleBuilder::new()
+            .host("127.0.0.1".to_string())
+            .port(server.port())
+            .key(KEY),
+        2,
+    );
+    let mut first = pool.get().unwrap();
+    let mut second = pool.get().unwrap();
+    assert_eq!(first.execute("first").unwrap(), "first");
+    assert_eq!(second.execute("second").unwrap(), "second");
+}
+
+#[test]
+fn wrong_key() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let other_key = [2_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    let mut console =
+        DNSDistConsole::new("127.0.0.1".to_string(), server.port(), other_key).unwrap();
+    match console.execute("showVersion()") {
+        Err(DNSDistConsoleError::ClosedBeforeKeyConfirmed) => {}
+        _ => panic!("expected a closed before key confirmed error"),
+    }
+    assert!(server.commands().is_empty());
+}
+
+#[test]
+fn execute_batch() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    let commands = vec![
+        "showVersion()".to_string(),
+        "showSecurityStatus()".to_string(),
+        "dumpStats()".to_string(),
+    ];
+    assert_eq!(console.execute_batch(&commands).unwrap(), commands);
+    assert_eq!(server.commands(), commands);
+}
+
+#[test]
+fn execute_batch_failure() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::start(KEY, |command| match command {
+        "dumpStats()" => "a".repeat(1024),
+        _ => "OK".to_string(),
+    })
+    .unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    console.set_max_response_size(512);
+    match console.execute_batch(&["showVersion()", "dumpStats()", "showServers()"]) {
+        Err(DNSDistConsoleError::BatchCommandFailed(idx, e)) => {
+            assert_eq!(idx, 1);
+            assert!(matches!(*e, DNSDistConsoleError::ResponseTooLarge(_)));
+        }
+        _ => panic!("expected a batch failure"),
+    }
+    assert_eq!(server.commands(), vec!["showVersion()", "dumpStats()"]);
+}
+
+#[test]
+fn execute_command_on() {
+    sodiumoxide::init().unwrap();
+
+    let first = MockConsoleServer::scripted(KEY, vec!["first".to_string()]).unwrap();
+    let second = MockConsoleServer::scripted(KEY, vec!["second".to_string()]).unwrap();
+    // the server is dropped right away, so nothing listens on that port anymore
+    let closed_port = MockConsoleServer::echo(KEY).unwrap().port();
+    let hosts = vec![
+        ("127.0.0.1".to_string(), first.port()),
+        ("127.0.0.1".to_string(), closed_port),
+        ("localhost".to_string(), second.port()),
+    ];
+
+    let results = lib_rs_dnsdist_console::execute_command_on(&hosts, KEY, "showVersion()");
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].0, "127.0.0.1");
+    assert_eq!(results[0].1.as_ref().unwrap(), "first");
+    assert!(results[1].1.is_err());
+    assert_eq!(results[2].0, "localhost");
+    assert_eq!(results[2].1.as_ref().unwrap(), "second");
+    assert_eq!(first.commands(), vec!["showVersion()"]);
+}
+
+#[test]
+fn reconnect() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::start(KEY, |command| match command {
+        "dumpStats()" => "a".repeat(1024),
+        _ => command.to_string(),
+    })
+    .unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    console.set_max_response_size(512);
+    console.set_read_only(true);
+
+    assert!(console.execute("dumpStats()").is_err());
+    assert!(!console.is_usable());
+
+    console.reconnect().unwrap();
+    assert!(console.is_usable());
+    assert_eq!(console.execute("showVersion()").unwrap(), "showVersion()");
+    // the settings have been preserved
+    assert!(matches!(
+        console.execute("setACL({})"),
+        Err(DNSDistConsoleError::CommandRejected(_))
+    ));
+    assert!(matches!(
+        console.execute("dumpStats()"),
+        Err(DNSDistConsoleError::ResponseTooLarge(_))
+    ));
+}
+
+#[test]
+fn server_version() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::scripted(KEY, vec!["dnsdist 1.7.2\n".to_string()]).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    assert_eq!(
+        console.server_version().unwrap(),
+        ServerVersion::new(1, 7, 2)
+    );
+    assert_eq!(server.commands(), vec!["showVersion()"]);
+    assert!(matches!(
+        console.server_version(),
+        Err(DNSDistConsoleError::ParseError(_))
+    ));
+}
+
+#[test]
+fn from_stream() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let stream = std::net::TcpStream::connect(server.local_addr()).unwrap();
+    let mut console = DNSDistConsole::from_stream(stream, KEY).unwrap();
+    assert_eq!(console.execute("showVersion()").unwrap(), "showVersion()");
+    assert!(matches!(
+        console.reconnect(),
+        Err(DNSDistConsoleError::MissingParameter(_))
+    ));
+}
+
+#[test]
+fn bytes_counters_include_handshake() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    let nonce = sodiumoxide::crypto::secretbox::NONCEBYTES as u64;
+    assert_eq!(console.bytes_sent(), nonce);
+    assert_eq!(console.bytes_received(), nonce);
+
+    console.reconnect().unwrap();
+    assert_eq!(console.bytes_sent(), 2 * nonce);
+    assert_eq!(console.bytes_received(), 2 * nonce);
+}
+
+#[test]
+fn peer_addr() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let console = DNSDistConsole::new("localhost".to_string(), server.port(), KEY).unwrap();
+    assert_eq!(console.peer_addr().unwrap(), server.local_addr());
+}
+
+#[test]
+fn nonces_stay_in_sync() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    let writing_counter = |console: &DNSDistConsole| {
+        let nonce = console.writing_nonce();
+        u32::from_be_bytes([nonce[0], nonce[1], nonce[2], nonce[3]])
+    };
+    let first = writing_counter(&console);
+
+    for idx in 0..100 {
+        let command = format!("getServer({})", idx);
+        assert_eq!(console.execute(&command).unwrap(), command);
+    }
+    assert_eq!(writing_counter(&console), first.wrapping_add(100));
+    assert_eq!(server.commands().len(), 100);
+}
+
+#[test]
+fn pipeline() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::start(KEY, |command| command.repeat(64)).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    // enough to need several windows, with responses much larger than the commands
+    let commands: Vec<String> = (0..300)
+        .map(|idx| format!("getServer({}) -- {}", idx, "a".repeat(1024)))
+        .collect();
+    let responses = console.pipeline(&commands).unwrap();
+    assert_eq!(responses.len(), commands.len());
+    for (command, response) in commands.iter().zip(&responses) {
+        assert_eq!(*response, command.repeat(64));
+    }
+    assert_eq!(server.commands(), commands);
+    assert!(console.is_usable());
+}
+
+#[test]
+fn pipeline_failure() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    console.set_read_only(true);
+    match console.pipeline(&["showVersion()", "setACL({})", "showServers()"]) {
+        Err(DNSDistConsoleError::BatchCommandFailed(idx, e)) => {
+            assert_eq!(idx, 1);
+            assert!(matches!(*e, DNSDistConsoleError::CommandRejected(_)));
+        }
+        _ => panic!("expected a batch failure"),
+    }
+    // the response to the first command has not been read
+    assert!(!console.is_usable());
+}
+
+#[test]
+fn security_status() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::scripted(KEY, vec!["2\n".to_string()]).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    assert_eq!(
+        console.security_status().unwrap(),
+        SecurityStatus::UpgradeRecommended
+    );
+    assert_eq!(server.commands(), vec!["showSecurityStatus()"]);
+}
+
+#[test]
+fn ping() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    console.set_read_only(true);
+    console.ping().unwrap();
+    assert_eq!(server.commands(), vec![""]);
+    assert_eq!(console.execute("showVersion()").unwrap(), "showVersion()");
+
+    drop(server);
+    let wrong_key = MockConsoleServer::echo([2; sodiumoxide::crypto::secretbox::KEYBYTES]).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), wrong_key.port(), KEY).unwrap();
+    assert!(console.ping().is_err());
+}
+
+#[test]
+fn pipeline_with_nagle() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsoleBuilder::new()
+        .host("127.0.0.1".to_string())
+        .port(server.port())
+        .key(KEY)
+        .nodelay(false)
+        .build()
+        .unwrap();
+    let commands: Vec<String> = (0..50).map(|idx| format!("getServer({})", idx)).collect();
+    assert_eq!(console.pipeline(&commands).unwrap(), commands);
+}
+
+#[test]
+fn connect_any() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let closed = MockConsoleServer::echo(KEY).unwrap().local_addr();
+    let mut console = DNSDistConsole::connect_any(&[closed, server.local_addr()], KEY).unwrap();
+    assert_eq!(console.peer_addr().unwrap(), server.local_addr());
+    assert_eq!(console.execute("showVersion()").unwrap(), "showVersion()");
+    // the reconnection tries the same addresses
+    console.reconnect().unwrap();
+    assert_eq!(console.peer_addr().unwrap(), server.local_addr());
+
+    assert!(matches!(
+        DNSDistConsole::connect_any(&[], KEY),
+        Err(DNSDistConsoleError::InvalidArgument(_))
+    ));
+    assert!(matches!(
+        DNSDistConsole::connect_any(&[closed], KEY),
+        Err(DNSDistConsoleError::TransportError(_, _))
+    ));
+}
+
+#[test]
+fn template() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::start(KEY, |command| match command {
+        "dumpStats()" => "a".repeat(1024),
+        _ => command.to_string(),
+    })
+    .unwrap();
+    let template = ConsoleTemplate::new()
+        .host("127.0.0.1".to_string())
+        .port(server.port())
+        .key(KEY)
+        .read_only(true)
+        .max_response_size(512)
+        .max_command_size(64)
+        .write_timeout(Some(std::time::Duration::from_secs(1)));
+    for _ in 0..2 {
+        let mut console = template.connect().unwrap();
+        assert_eq!(console.execute("showVersion()").unwrap(), "showVersion()");
+        assert!(matches!(
+            console.execute("setACL({})"),
+            Err(DNSDistConsoleError::CommandRejected(_))
+        ));
+        assert!(matches!(
+            console.execute(format!("showServers({})", "1".repeat(64))),
+            Err(DNSDistConsoleError::CommandTooLarge(_))
+        ));
+        assert!(matches!(
+            console.execute("dumpStats()"),
+            Err(DNSDistConsoleError::ResponseTooLarge(_))
+        ));
+    }
+    assert_eq!(server.commands().len(), 4);
+}
+
+#[test]
+fn execute_checked() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::scripted(
+        KEY,
+        vec![
+            "dnsdist 1.7.2\n".to_string(),
+            "Error: [string \"chunk\"]:1: attempt to call a nil value (global 'shoServers')\n"
+                .to_string(),
+            "[string \"chunk\"]:1: boom\nstack traceback:\n\t[C]: in function 'error'\n"
+                .to_string(),
+            "no Error: here\n".to_string(),
+        ],
+    )
+    .unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    assert_eq!(
+        console.execute_checked("showVersion()").unwrap(),
+        "dnsdist 1.7.2\n"
+    );
+    match console.execute_checked("shoServers()") {
+        Err(DNSDistConsoleError::CommandError(response)) => {
+            assert!(response.starts_with("Error: "))
+        }
+        _ => panic!("expected a command error"),
+    }
+    assert!(matches!(
+        console.execute_checked("error('boom')"),
+        Err(DNSDistConsoleError::CommandError(_))
+    ));
+    assert!(console.is_usable());
+    assert_eq!(
+        console.execute_checked("print('no Error: here')").unwrap(),
+        "no Error: here\n"
+    );
+}
+
+#[test]
+fn receive_into() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::start(KEY, |command| command.repeat(100)).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    let mut buf = "previous content".to_string();
+    for command in &["showVersion()", "dumpStats()", "showServers()"] {
+        console.send(command).unwrap();
+        console.receive_into(&mut buf).unwrap();
+        assert_eq!(buf, command.repeat(100));
+    }
+
+    // the allocation is reused once large enough
+    let capacity = buf.capacity();
+    console.send("getServer(0)").unwrap();
+    console.receive_into(&mut buf).unwrap();
+    assert_eq!(buf, "getServer(0)".repeat(100));
+    assert_eq!(buf.capacity(), capacity);
+}
+
+#[test]
+fn reconnect_with_key() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    let new_key = [2_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+
+    // the server still uses the old key, so the current connection is kept
+    assert!(console.reconnect_with_key(new_key).is_err());
+    assert_eq!(console.execute("showVersion()").unwrap(), "showVersion()");
+
+    let rotated = MockConsoleServer::echo(new_key).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), rotated.port(), KEY).unwrap();
+    console.reconnect_with_key(new_key).unwrap();
+    assert_eq!(console.execute("showVersion()").unwrap(), "showVersion()");
+    // later reconnections use the new key
+    console.reconnect().unwrap();
+    assert_eq!(console.execute("showVersion()").unwrap(), "showVersion()");
+}
+
+#[test]
+fn wire_tap() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::start(KEY, |_| "dnsdist 1.7.2\n".to_string()).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    let frames = Arc::new(Mutex::new(Vec::new()));
+    let tapped = frames.clone();
+    console.set_wire_tap(Some(Box::new(move |direction, bytes: &[u8]| {
+        tapped.lock().unwrap().push((direction, bytes.to_vec()))
+    })));
+    let bytes_sent = console.bytes_sent();
+    let bytes_received = console.bytes_received();
+    assert_eq!(console.execute("showVersion()").unwrap(), "dnsdist 1.7.2\n");
+
+    let frames = frames.lock().unwrap();
+    assert_eq!(frames.len(), 2);
+    let mac = sodiumoxide::crypto::secretbox::MACBYTES;
+    for (frame, direction, size) in [
+        (&frames[0], Direction::Sent, "showVersion()".len()),
+        (&frames[1], Direction::Received, "dnsdist 1.7.2\n".len()),
+    ] {
+        assert_eq!(frame.0, direction);
+        assert_eq!(frame.1.len(), 4 + mac + size);
+        assert_eq!(frame.1[..4], ((mac + size) as u32).to_be_bytes());
+        // the plaintext is never shown
+        assert!(!frame.1.windows(7).any(|window| window == b"dnsdist"));
+        assert!(!frame.1.windows(4).any(|window| window == b"show"));
+    }
+    assert_eq!(console.bytes_sent() - bytes_sent, frames[0].1.len() as u64);
+    assert_eq!(
+        console.bytes_received() - bytes_received,
+        frames[1].1.len() as u64
+    );
+    drop(frames);
+
+    console.set_wire_tap(None);
+    console.execute("showVersion()").unwrap();
+}
+
+#[test]
+fn address_family() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let builder = |host: &str, family| {
+        DNSDistConsoleBuilder::new()
+            .host(host.to_string())
+            .port(server.port())
+            .key(KEY)
+            .address_family(family)
+    };
+    let console = builder("localhost", AddressFamily::V4).build().unwrap();
+    assert_eq!(console.peer_addr().unwrap(), server.local_addr());
+    // the server only listens on IPv4
+    assert!(builder("localhost", AddressFamily::V6).build().is_err());
+    assert!(matches!(
+        builder("127.0.0.1", AddressFamily::V6).build(),
+        Err(DNSDistConsoleError::InvalidArgument(_))
+    ));
+}
+
+#[test]
+fn execute_timed() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::start(KEY, |command| {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        command.to_string()
+    })
+    .unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    let (response, elapsed) = console.execute_timed("showVersion()").unwrap();
+    assert_eq!(response, "showVersion()");
+    assert!(elapsed >= std::time::Duration::from_millis(50));
+}
+
+#[test]
+fn execute_file() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    let path = std::env::temp_dir().join(format!("execute_file_{}.lua", std::process::id()));
+    let snippet = "  for i = 1, 2 do\n    print(\"server  \" .. i)\n  end\n\n";
+    std::fs::write(&path, snippet).unwrap();
+    let response = console.execute_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(response.unwrap(), snippet);
+    assert_eq!(server.commands(), vec![snippet]);
+
+    assert!(matches!(
+        console.execute_file(&path),
+        Err(DNSDistConsoleError::IOError(_))
+    ));
+    assert_eq!(server.commands().len(), 1);
+}
+
+#[test]
+fn into_stream() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    assert_eq!(console.execute("showVersion()").unwrap(), "showVersion()");
+    let reading_nonce = console.reading_nonce();
+    let writing_nonce = console.writing_nonce();
+    let stream = console.into_stream().unwrap();
+    assert_eq!(stream.peer_addr().unwrap(), server.local_addr());
+
+    // the stream was not shut down, so the session can be resumed
+    let mut console = DNSDistConsole::from_parts(stream, KEY, reading_nonce, writing_nonce);
+    assert_eq!(console.execute("showServers()").unwrap(), "showServers()");
+    assert_eq!(server.commands(), vec!["showVersion()", "showServers()"]);
+}
+
+#[test]
+fn execute_after_responses_timeout() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    console
+        .set_io_timeouts(Some(std::time::Duration::from_millis(50)), None)
+        .unwrap();
+    console.send("showServers()").unwrap();
+    let responses: Vec<String> = console.responses().map(|r| r.unwrap()).collect();
+    assert_eq!(responses, vec!["showServers()"]);
+
+    // nothing was left pending, so the connection is still in sync
+    assert!(console.is_usable());
+    assert_eq!(console.execute("showVersion()").unwrap(), "showVersion()");
+}
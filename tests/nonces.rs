@@ -0,0 +1,327 @@
+use std::convert::TryInto;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use lib_rs_dnsdist_console::{derive_session_nonces, ConsoleProtocol, DNSDistConsole};
+use sodiumoxide::crypto::secretbox;
+
+const KEY: [u8; secretbox::KEYBYTES] = [1; secretbox::KEYBYTES];
+
+fn connected_pair() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    client.set_nodelay(true).unwrap();
+    server.set_nodelay(true).unwrap();
+    (client, server)
+}
+
+#[test]
+fn handshake_nonces() {
+    sodiumoxide::init().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let mut server_nonce = [0_u8; secretbox::NONCEBYTES];
+    for (idx, byte) in server_nonce.iter_mut().enumerate() {
+        *byte = idx as u8;
+    }
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut client_nonce = [0_u8; secretbox::NONCEBYTES];
+        stream.read_exact(&mut client_nonce).unwrap();
+        stream.write_all(&server_nonce).unwrap();
+        client_nonce
+    });
+
+    let console = DNSDistConsole::new("127.0.0.1".to_string(), port, KEY).unwrap();
+    let client_nonce = server.join().unwrap();
+
+    let half = secretbox::NONCEBYTES / 2;
+    let reading_nonce = console.reading_nonce();
+    assert_eq!(reading_nonce[..half], client_nonce[..half]);
+    assert_eq!(reading_nonce[half..], server_nonce[half..]);
+    let writing_nonce = console.writing_nonce();
+    assert_eq!(writing_nonce[..half], server_nonce[..half]);
+    assert_eq!(writing_nonce[half..], client_nonce[half..]);
+}
+
+/// The console protocol, as implemented by dnsdist in `dnsdist-console.cc`: right after
+/// connecting, the client sends a random nonce of `NONCEBYTES` bytes and the server answers with
+/// its own. Both sides then build two session nonces with `SodiumNonce::merge(lower, higher)`,
+/// which takes the first half of `lower` and the second half of `higher`: the client reads with
+/// `merge(ours, theirs)` and writes with `merge(theirs, ours)`, the server the other way around.
+#[test]
+fn session_nonces_derivation() {
+    let client_nonce: [u8; secretbox::NONCEBYTES] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+    ];
+    let server_nonce: [u8; secretbox::NONCEBYTES] = [
+        0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x8c, 0x8d, 0x8e,
+        0x8f, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97,
+    ];
+
+    let (reading_nonce, writing_nonce) = derive_session_nonces(&client_nonce, &server_nonce);
+    assert_eq!(
+        reading_nonce,
+        [
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x8c, 0x8d,
+            0x8e, 0x8f, 0x90, 0x91, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97,
+        ]
+    );
+    assert_eq!(
+        writing_nonce,
+        [
+            0x80, 0x81, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89, 0x8a, 0x8b, 0x0c, 0x0d,
+            0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17,
+        ]
+    );
+}
+
+/// Since dnsdist derives its own nonces with the same function, seen from its side of the
+/// handshake, each side has to read with the nonce the other one writes with. Swapping halves or
+/// nonces anywhere would break this, while still producing plausible-looking nonces.
+#[test]
+fn nonce_halves_not_swapped() {
+    sodiumoxide::init().unwrap();
+
+    let mut client_nonce = [0_u8; secretbox::NONCEBYTES];
+    let mut server_nonce = [0_u8; secretbox::NONCEBYTES];
+    for idx in 0..secretbox::NONCEBYTES {
+        client_nonce[idx] = 0x10 + idx as u8;
+        server_nonce[idx] = 0xa0 + idx as u8;
+    }
+    let half = secretbox::NONCEBYTES / 2;
+
+    let mut client = ConsoleProtocol::from_handshake(KEY, &client_nonce, &server_nonce).unwrap();
+    let mut server = ConsoleProtocol::from_handshake(KEY, &server_nonce, &client_nonce).unwrap();
+    assert_ne!(client.reading_nonce(), client.writing_nonce());
+    assert_eq!(client.reading_nonce()[..half], client_nonce[..half]);
+    assert_eq!(client.reading_nonce()[half..], server_nonce[half..]);
+    assert_eq!(client.writing_nonce()[..half], server_nonce[..half]);
+    assert_eq!(client.writing_nonce()[half..], client_nonce[half..]);
+    assert_eq!(server.reading_nonce(), client.writing_nonce());
+    assert_eq!(server.writing_nonce(), client.reading_nonce());
+
+    // and the nonces stay in sync in both directions
+    for idx in 0..3 {
+        let command = format!("command {}", idx);
+        let frame = client.encode_command(&command).unwrap();
+        assert_eq!(server.decode_response(&frame[4..]).unwrap(), command);
+        let frame = server.encode_command(&command).unwrap();
+        assert_eq!(client.decode_response(&frame[4..]).unwrap(), command);
+    }
+}
+
+#[test]
+fn handshake_wire_format() {
+    sodiumoxide::init().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server_nonce = [7_u8; secretbox::NONCEBYTES];
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut client_nonce = [0_u8; secretbox::NONCEBYTES];
+        stream.read_exact(&mut client_nonce).unwrap();
+
+        // the client must not send anything else until it has received our nonce
+        thread::sleep(Duration::from_millis(50));
+        stream.set_nonblocking(true).unwrap();
+        let mut extra = [0_u8; 1];
+        match stream.read(&mut extra) {
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            other => panic!("unexpected data after the client nonce: {:?}", other),
+        }
+        stream.set_nonblocking(false).unwrap();
+
+        // send our nonce in several segments, without any banner
+        for chunk in server_nonce.chunks(10) {
+            stream.write_all(chunk).unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // the first bytes after the handshake are the first command frame
+        let mut len_buffer = [0_u8; 4];
+        stream.read_exact(&mut len_buffer).unwrap();
+        let mut ciphertext = vec![0_u8; u32::from_be_bytes(len_buffer).try_into().unwrap()];
+        stream.read_exact(&mut ciphertext).unwrap();
+        (client_nonce, ciphertext)
+    });
+
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), port, KEY).unwrap();
+    let writing_nonce = console.writing_nonce();
+    console.send("showVersion()").unwrap();
+    let (client_nonce, ciphertext) = server.join().unwrap();
+
+    let half = secretbox::NONCEBYTES / 2;
+    assert_eq!(writing_nonce[..half], server_nonce[..half]);
+    assert_eq!(writing_nonce[half..], client_nonce[half..]);
+    let cleartext = secretbox::open(
+        &ciphertext,
+        &secretbox::Nonce(writing_nonce),
+        &secretbox::Key(KEY),
+    )
+    .unwrap();
+    assert_eq!(cleartext, b"showVersion()");
+}
+
+#[test]
+fn resume_from_parts() {
+    sodiumoxide::init().unwrap();
+
+    let (client, mut server) = connected_pair();
+    let reading_nonce = [3_u8; secretbox::NONCEBYTES];
+    let writing_nonce = [4_u8; secretbox::NONCEBYTES];
+    let mut console = DNSDistConsole::from_parts(client, KEY, reading_nonce, writing_nonce);
+    assert_eq!(console.reading_nonce(), reading_nonce);
+    assert_eq!(console.writing_nonce(), writing_nonce);
+
+    console.send("showVersion()").unwrap();
+
+    let mut len_buffer = [0_u8; 4];
+    server.read_exact(&mut len_buffer).unwrap();
+    let mut ciphertext = vec![0_u8; u32::from_be_bytes(len_buffer).try_into().unwrap()];
+    server.read_exact(&mut ciphertext).unwrap();
+    let cleartext = secretbox::open(
+        &ciphertext,
+        &secretbox::Nonce(writing_nonce),
+        &secretbox::Key(KEY),
+    )
+    .unwrap();
+    assert_eq!(cleartext, b"showVersion()");
+    assert_ne!(console.writing_nonce(), writing_nonce);
+    assert_eq!(console.reading_nonce(), reading_nonce);
+}
+
+/// Increments a nonce the way dnsdist does
+fn increment(nonce: &mut [u8; secretbox::NONCEBYTES]) {
+    let value = u32::from_be_bytes(nonce[..4].try_into().unwrap()).wrapping_add(1);
+    nonce[..4].copy_from_slice(&value.to_be_bytes());
+}
+
+/// Spawns a server answering every command with "response to " followed by the command, until
+/// the connection is closed
+fn spawn_echo_server(
+    mut server: TcpStream,
+    reading_nonce: [u8; secretbox::NONCEBYTES],
+    writing_nonce: [u8; secretbox::NONCEBYTES],
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        // the server reads with the nonce we write with, and the other way around
+        let (mut reading_nonce, mut writing_nonce) = (writing_nonce, reading_nonce);
+        loop {
+            let mut len_buffer = [0_u8; 4];
+            if server.read_exact(&mut len_buffer).is_err() {
+                break;
+            }
+            let mut ciphertext = vec![0_u8; u32::from_be_bytes(len_buffer).try_into().unwrap()];
+            server.read_exact(&mut ciphertext).unwrap();
+            let command = secretbox::open(
+                &ciphertext,
+                &secretbox::Nonce(reading_nonce),
+                &secretbox::Key(KEY),
+            )
+            .unwrap();
+            increment(&mut reading_nonce);
+
+            let mut response = b"response to ".to_vec();
+            response.extend_from_slice(&command);
+            let ciphertext = secretbox::seal(
+                &response,
+                &secretbox::Nonce(writing_nonce),
+                &secretbox::Key(KEY),
+            );
+            increment(&mut writing_nonce);
+            let len: u32 = ciphertext.len().try_into().unwrap();
+            server.write_all(&len.to_be_bytes()).unwrap();
+            server.write_all(&ciphertext).unwrap();
+        }
+    })
+}
+
+#[test]
+fn multiple_commands() {
+    sodiumoxide::init().unwrap();
+
+    let (client, server) = connected_pair();
+    let reading_nonce = [5_u8; secretbox::NONCEBYTES];
+    let writing_nonce = [6_u8; secretbox::NONCEBYTES];
+    let mut console = DNSDistConsole::from_parts(client, KEY, reading_nonce, writing_nonce);
+    let server = spawn_echo_server(server, reading_nonce, writing_nonce);
+
+    for idx in 0..10 {
+        console.send(format!("command {}", idx)).unwrap();
+        assert_eq!(
+            console.receive().unwrap(),
+            format!("response to command {}", idx)
+        );
+    }
+    drop(console);
+    server.join().unwrap();
+}
+
+#[test]
+fn send_and_discard() {
+    sodiumoxide::init().unwrap();
+
+    let (client, server) = connected_pair();
+    let reading_nonce = [5_u8; secretbox::NONCEBYTES];
+    let writing_nonce = [6_u8; secretbox::NONCEBYTES];
+    let mut console = DNSDistConsole::from_parts(client, KEY, reading_nonce, writing_nonce);
+    let server = spawn_echo_server(server, reading_nonce, writing_nonce);
+
+    for idx in 0..3 {
+        console
+            .send_and_discard(format!("ignored {}", idx))
+            .unwrap();
+    }
+    // the discarded responses have been consumed, so the next one is ours
+    assert_eq!(
+        console.execute("showVersion()").unwrap(),
+        "response to showVersion()"
+    );
+    drop(console);
+    server.join().unwrap();
+}
+
+fn written_nonce_after_send(
+    writing_nonce: [u8; secretbox::NONCEBYTES],
+) -> [u8; secretbox::NONCEBYTES] {
+    let (client, _server) = connected_pair();
+    let mut console =
+        DNSDistConsole::from_parts(client, KEY, [0; secretbox::NONCEBYTES], writing_nonce);
+    console.send("showVersion()").unwrap();
+    console.writing_nonce()
+}
+
+#[test]
+fn increment_carries_within_counter() {
+    sodiumoxide::init().unwrap();
+
+    let mut nonce = [7_u8; secretbox::NONCEBYTES];
+    nonce[..4].copy_from_slice(&[0x00, 0x00, 0x00, 0xff]);
+    let mut expected = nonce;
+    expected[..4].copy_from_slice(&[0x00, 0x00, 0x01, 0x00]);
+    assert_eq!(written_nonce_after_send(nonce), expected);
+
+    nonce[..4].copy_from_slice(&[0x00, 0xff, 0xff, 0xff]);
+    expected[..4].copy_from_slice(&[0x01, 0x00, 0x00, 0x00]);
+    assert_eq!(written_nonce_after_send(nonce), expected);
+}
+
+#[test]
+fn increment_rolls_over() {
+    sodiumoxide::init().unwrap();
+
+    // like dnsdist, the 32-bit counter wraps around without touching the remaining bytes
+    let mut nonce = [7_u8; secretbox::NONCEBYTES];
+    nonce[..4].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+    let mut expected = nonce;
+    expected[..4].copy_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+    assert_eq!(written_nonce_after_send(nonce), expected);
+}
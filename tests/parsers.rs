@@ -0,0 +1,345 @@
+use lib_rs_dnsdist_console::{
+    parse_acl, parse_dump_stats, parse_security_status, parse_show_frontends, parse_show_pools,
+    parse_show_servers, parse_statistics, parse_version, Frontend, Netmask, ParseError, PoolInfo,
+    SecurityStatus, ServerVersion,
+};
+
+#[test]
+fn show_servers() {
+    let output = "\
+#   Name                 Address                       State     Qps    Qlim Ord Wt    Queries   Drops Drate   Lat Outstanding Pools
+0   127.0.0.1:53         127.0.0.1:53                     up    12.0       0   1  1        541       2   0.0   1.5           3 
+1   backend2             [2001:db8::1]:53               DOWN     0.0     100   2  5          0       0   0.0   0.0           0 abuse tcp
+All                                                             12.0                       541       2                         
+";
+    let servers = parse_show_servers(output).unwrap();
+    assert_eq!(servers.len(), 2);
+
+    assert_eq!(servers[0].id, 0);
+    assert_eq!(servers[0].name, "127.0.0.1:53");
+    assert_eq!(servers[0].address, "127.0.0.1:53");
+    assert_eq!(servers[0].state, "up");
+    assert_eq!(servers[0].qps, 12.0);
+    assert_eq!(servers[0].qps_limit, 0);
+    assert_eq!(servers[0].order, 1);
+    assert_eq!(servers[0].weight, 1);
+    assert_eq!(servers[0].queries, 541);
+    assert_eq!(servers[0].drops, 2);
+    assert_eq!(servers[0].latency, 1.5);
+    assert_eq!(servers[0].tcp_latency, None);
+    assert_eq!(servers[0].outstanding, 3);
+    assert!(servers[0].pools.is_empty());
+
+    assert_eq!(servers[1].name, "backend2");
+    assert_eq!(servers[1].address, "[2001:db8::1]:53");
+    assert_eq!(servers[1].state, "DOWN");
+    assert_eq!(servers[1].qps_limit, 100);
+    assert_eq!(servers[1].weight, 5);
+    assert_eq!(servers[1].pools, vec!["abuse", "tcp"]);
+}
+
+#[test]
+fn show_servers_tcp_latency() {
+    let output = "\
+#   Name                 Address                       State     Qps    Qlim Ord Wt    Queries   Drops Drate   Lat   TCP Outstanding Pools
+0   127.0.0.1:53         127.0.0.1:53                     up     0.0       0   1  1          0       0   0.0   0.3   2.5           0 
+";
+    let servers = parse_show_servers(output).unwrap();
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].latency, 0.3);
+    assert_eq!(servers[0].tcp_latency, Some(2.5));
+}
+
+#[test]
+fn show_servers_invalid() {
+    assert_eq!(
+        parse_show_servers("No servers").unwrap_err(),
+        ParseError::MissingHeader
+    );
+    let output = "\
+#   Name                 Address                       State     Qps    Qlim Ord Wt    Queries   Drops Drate   Lat Outstanding Pools
+0   127.0.0.1:53         127.0.0.1:53                     up     abc       0   1  1          0       0   0.0   0.0           0 
+";
+    match parse_show_servers(output) {
+        Err(ParseError::InvalidLine(2, _)) => {}
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+const DUMP_STATS: &str = "\
+acl-drops              \t          3\tlatency0-1             \t        120
+cache-hits             \t         10\tlatency1-10            \t          4
+cache-misses           \t         32\tlatency10-50           \t          0
+cpu-sys-msec           \t        512\tlatency100-1000        \t          0
+downstream-send-errors \t          0\tlatency50-100          \t          0
+downstream-timeouts    \t          1\tno-policy              \t          0
+latency-avg100         \t      231.5\tqueries                \t        145
+responses              \t        140\trule-drop              \t          2
+rule-nxdomain          \t          0\trule-refused           \t          0
+self-answered          \t          0\tservfail-responses     \t          5
+uptime                 \t       3600
+";
+
+#[test]
+fn dump_stats() {
+    let stats = parse_dump_stats(DUMP_STATS).unwrap();
+    assert_eq!(stats.len(), 21);
+    assert_eq!(stats["acl-drops"], 3.0);
+    assert_eq!(stats["latency0-1"], 120.0);
+    assert_eq!(stats["latency-avg100"], 231.5);
+    assert_eq!(stats["uptime"], 3600.0);
+}
+
+#[test]
+fn statistics() {
+    let stats = parse_statistics(DUMP_STATS).unwrap();
+    assert_eq!(stats.queries, 145);
+    assert_eq!(stats.responses, 140);
+    assert_eq!(stats.servfail_responses, 5);
+    assert_eq!(stats.acl_drops, 3);
+    assert_eq!(stats.rule_drop, 2);
+    assert_eq!(stats.downstream_timeouts, 1);
+    assert_eq!(stats.cache_hits, 10);
+    assert_eq!(stats.cache_misses, 32);
+    assert_eq!(stats.latency_avg100, 231.5);
+    assert_eq!(stats.uptime, 3600);
+
+    assert_eq!(
+        parse_statistics("queries 1 responses 1"),
+        Err(ParseError::MissingCounter("servfail-responses".to_string()))
+    );
+}
+
+#[test]
+fn statistics_invalid_counters() {
+    // a large counter is kept exactly instead of being rounded through a float
+    let output = DUMP_STATS.replace("        145\n", " 9007199254740993\n");
+    assert_eq!(parse_statistics(&output).unwrap().queries, 9007199254740993);
+
+    for value in &["-1", "NaN", "inf", "1.5", "1e3", "18446744073709551616"] {
+        let output = DUMP_STATS.replace("        145\n", &format!(" {}\n", value));
+        assert!(
+            matches!(
+                parse_statistics(&output),
+                Err(ParseError::InvalidLine(_, _))
+            ),
+            "{}",
+            value
+        );
+    }
+}
+
+#[test]
+fn dump_stats_invalid() {
+    assert!(matches!(
+        parse_dump_stats("queries 1\nresponses"),
+        Err(ParseError::InvalidLine(2, _))
+    ));
+    assert!(matches!(
+        parse_dump_stats("queries 1 responses many"),
+        Err(ParseError::InvalidLine(1, _))
+    ));
+}
+
+#[test]
+fn version() {
+    assert_eq!(
+        parse_version("dnsdist 1.6.1\n").unwrap(),
+        ServerVersion::new(1, 6, 1)
+    );
+    let rc = parse_version("dnsdist 1.7.0-rc1\n").unwrap();
+    assert_eq!(rc.pre_release.as_deref(), Some("rc1"));
+    assert_eq!(rc.to_string(), "1.7.0-rc1");
+
+    assert!(rc < ServerVersion::new(1, 7, 0));
+    assert!(rc > ServerVersion::new(1, 6, 1));
+    assert!(ServerVersion::new(1, 10, 0) > ServerVersion::new(1, 9, 2));
+
+    assert!(parse_version("PowerDNS Recursor 4.5.0").is_err());
+    assert!(parse_version("dnsdist 1.6").is_err());
+    assert!(parse_version("dnsdist 1.6.1.2").is_err());
+}
+
+#[test]
+fn security_status() {
+    assert_eq!(
+        parse_security_status("0\n").unwrap(),
+        SecurityStatus::Unknown
+    );
+    assert_eq!(parse_security_status("1\n").unwrap(), SecurityStatus::Ok);
+    assert_eq!(
+        parse_security_status("2\n").unwrap(),
+        SecurityStatus::UpgradeRecommended
+    );
+    assert_eq!(
+        parse_security_status("3\n").unwrap(),
+        SecurityStatus::UpgradeRequired
+    );
+    assert!(parse_security_status("4\n").is_err());
+    assert!(parse_security_status("").is_err());
+}
+
+#[test]
+fn show_frontends() {
+    let output = "\
+#   Address                 Protocol    Queries
+0   127.0.0.1:53            UDP              12
+1   127.0.0.1:53            TCP               3
+2   [::1]:8443              DoH               0
+3   127.0.0.1:8443          UDP (DNSCrypt)    7
+";
+    let frontends = parse_show_frontends(output).unwrap();
+    assert_eq!(frontends.len(), 4);
+    assert_eq!(
+        frontends[0],
+        Frontend {
+            id: 0,
+            address: "127.0.0.1:53".to_string(),
+            protocol: "UDP".to_string(),
+            queries: 12,
+            drops: None,
+        }
+    );
+    assert_eq!(frontends[2].address, "[::1]:8443");
+    assert_eq!(frontends[2].protocol, "DoH");
+    assert_eq!(frontends[3].protocol, "UDP (DNSCrypt)");
+    assert_eq!(frontends[3].queries, 7);
+}
+
+#[test]
+fn show_frontends_with_drops() {
+    let output = "\
+#   Address                 Protocol    Queries   Drops
+0   127.0.0.1:853           DoT              42       1
+1   127.0.0.1:8443          TCP (DNSCrypt)    5       0
+";
+    let frontends = parse_show_frontends(output).unwrap();
+    assert_eq!(frontends[0].drops, Some(1));
+    assert_eq!(frontends[1].protocol, "TCP (DNSCrypt)");
+    assert_eq!(frontends[1].queries, 5);
+    assert_eq!(frontends[1].drops, Some(0));
+}
+
+#[test]
+fn show_frontends_invalid() {
+    assert!(matches!(
+        parse_show_frontends("0   127.0.0.1:53   UDP   12\n"),
+        Err(ParseError::MissingHeader)
+    ));
+    assert!(matches!(
+        parse_show_frontends("#   Address   Queries\n"),
+        Err(ParseError::MissingColumn(_))
+    ));
+    assert!(matches!(
+        parse_show_frontends("#   Address   Protocol   Queries\n0   127.0.0.1:53   UDP   many\n"),
+        Err(ParseError::InvalidLine(2, _))
+    ));
+    assert!(matches!(
+        parse_show_frontends("#   Address   Protocol   Queries\n0   127.0.0.1:53\n"),
+        Err(ParseError::InvalidLine(2, _))
+    ));
+}
+
+#[test]
+fn show_pools() {
+    let output = "\
+Name                 Cache      ServerPolicy         Servers
+                                leastOutstanding     127.0.0.1:53, ns2 192.0.2.2:53
+abuse                12/10000   firstAvailable       192.0.2.1:53
+empty                           roundrobin
+";
+    let pools = parse_show_pools(output).unwrap();
+    assert_eq!(pools.len(), 3);
+    assert_eq!(
+        pools[0],
+        PoolInfo {
+            name: "".to_string(),
+            cache: None,
+            policy: "leastOutstanding".to_string(),
+            servers: vec!["127.0.0.1:53".to_string(), "ns2 192.0.2.2:53".to_string()],
+        }
+    );
+    assert_eq!(pools[1].name, "abuse");
+    assert_eq!(pools[1].cache, Some((12, 10000)));
+    assert_eq!(pools[1].policy, "firstAvailable");
+    assert_eq!(pools[1].servers, vec!["192.0.2.1:53"]);
+    assert_eq!(pools[2].cache, None);
+    assert_eq!(pools[2].policy, "roundrobin");
+    assert!(pools[2].servers.is_empty());
+}
+
+#[test]
+fn show_pools_invalid() {
+    assert!(matches!(
+        parse_show_pools("abuse   firstAvailable   192.0.2.1:53\n"),
+        Err(ParseError::MissingHeader)
+    ));
+    assert!(matches!(
+        parse_show_pools("Name   Cache   Servers\n"),
+        Err(ParseError::MissingColumn(_))
+    ));
+    assert!(matches!(
+        parse_show_pools("Name   Cache   ServerPolicy   Servers\nabuse   12/100\n"),
+        Err(ParseError::InvalidLine(2, _))
+    ));
+}
+
+#[test]
+fn acl() {
+    let output = "\
+127.0.0.0/8
+
+10.0.0.0/8
+::1/128
+fe80::/10
+192.0.2.1
+2001:db8::1
+0.0.0.0/0
+";
+    let networks = parse_acl(output).unwrap();
+    let printed: Vec<String> = networks.iter().map(|network| network.to_string()).collect();
+    assert_eq!(
+        printed,
+        vec![
+            "127.0.0.0/8",
+            "10.0.0.0/8",
+            "::1/128",
+            "fe80::/10",
+            "192.0.2.1/32",
+            "2001:db8::1/128",
+            "0.0.0.0/0"
+        ]
+    );
+    assert_eq!(networks[3].prefix_len(), 10);
+    assert!(networks[0].contains(&"127.1.2.3".parse().unwrap()));
+    assert!(!networks[0].contains(&"128.0.0.1".parse().unwrap()));
+    assert!(networks[3].contains(&"febf::1".parse().unwrap()));
+    assert!(!networks[3].contains(&"fec0::1".parse().unwrap()));
+    assert!(!networks[3].contains(&"127.0.0.1".parse().unwrap()));
+    assert!(networks[6].contains(&"203.0.113.7".parse().unwrap()));
+    assert!(!networks[6].contains(&"::1".parse().unwrap()));
+    assert_eq!(
+        Netmask::new("::".parse().unwrap(), 0).unwrap().to_string(),
+        "::/0"
+    );
+}
+
+#[test]
+fn acl_invalid() {
+    for (output, line) in &[
+        ("127.0.0.0/8\n10.0.0.0/33\n", 2),
+        ("2001:db8::/129\n", 1),
+        ("192.0.2.1/24\n", 1),
+        ("fe80::1/10\n", 1),
+        ("192.0.2.0/\n", 1),
+        ("192.0.2.0/+24\n", 1),
+        ("192.0.2.0/24/8\n", 1),
+        ("example.com/24\n", 1),
+        ("\n\n300.0.0.0/8\n", 3),
+    ] {
+        match parse_acl(output) {
+            Err(ParseError::InvalidLine(idx, _)) => assert_eq!(idx, *line, "{}", output),
+            other => panic!("unexpected result for {}: {:?}", output, other),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "insecure-plaintext")]
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+use lib_rs_dnsdist_console::DNSDistConsole;
+
+#[test]
+fn plaintext_exchange() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut len_buffer = [0_u8; 4];
+        stream.read_exact(&mut len_buffer).unwrap();
+        let mut command = vec![0_u8; u32::from_be_bytes(len_buffer) as usize];
+        stream.read_exact(&mut command).unwrap();
+        assert_eq!(command, b"showVersion()");
+
+        let response = b"dnsdist 1.6.1\n";
+        stream
+            .write_all(&(response.len() as u32).to_be_bytes())
+            .unwrap();
+        stream.write_all(response).unwrap();
+    });
+
+    let mut console = DNSDistConsole::connect_plaintext("127.0.0.1".to_string(), port).unwrap();
+    assert_eq!(console.execute("showVersion()").unwrap(), "dnsdist 1.6.1\n");
+    server.join().unwrap();
+}
@@ -0,0 +1,127 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use lib_rs_dnsdist_console::{ConsolePool, DNSDistConsoleBuilder};
+
+/// Starts a server completing the handshake on every incoming connection
+fn start_server() -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        let mut streams = Vec::new();
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut client_nonce = [0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES];
+            stream.read_exact(&mut client_nonce).unwrap();
+            stream
+                .write_all(&[0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES])
+                .unwrap();
+            streams.push(stream);
+        }
+    });
+    port
+}
+
+fn builder(port: u16) -> DNSDistConsoleBuilder {
+    DNSDistConsoleBuilder::new()
+        .host("127.0.0.1".to_string())
+        .port(port)
+        .key([1_u8; sodiumoxide::crypto::secretbox::KEYBYTES])
+}
+
+#[test]
+fn reuse_connections() {
+    sodiumoxide::init().unwrap();
+
+    let pool = ConsolePool::new(builder(start_server()), 2);
+    assert_eq!(pool.open_connections(), 0);
+    let first = pool.get().unwrap();
+    let second = pool.get().unwrap();
+    assert_eq!(pool.open_connections(), 2);
+    drop(first);
+    let third = pool.get().unwrap();
+    assert_eq!(pool.open_connections(), 2);
+    drop(second);
+    drop(third);
+    assert_eq!(pool.open_connections(), 2);
+}
+
+#[test]
+fn wait_for_connection() {
+    sodiumoxide::init().unwrap();
+
+    let pool = ConsolePool::new(builder(start_server()), 1);
+    let first = pool.get().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let _second = pool.get().unwrap();
+            sender.send(()).unwrap();
+        });
+        // the pool is full, so the other thread has to wait
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+        drop(first);
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    });
+    assert_eq!(pool.open_connections(), 1);
+}
+
+#[test]
+fn connection_failure() {
+    // nothing is listening on this port once the listener is dropped
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let pool = ConsolePool::new(builder(port), 1);
+    assert!(pool.get().is_err());
+    assert_eq!(pool.open_connections(), 0);
+}
+
+#[test]
+fn replace_closed_connection() {
+    sodiumoxide::init().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let (close, wait_close) = mpsc::channel();
+    let (accepted, wait_accepted) = mpsc::channel();
+    thread::spawn(move || {
+        let mut streams = Vec::new();
+        let mut first = true;
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut client_nonce = [0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES];
+            stream.read_exact(&mut client_nonce).unwrap();
+            stream
+                .write_all(&[0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES])
+                .unwrap();
+            accepted.send(()).unwrap();
+            if first {
+                // close the first connection once it is back in the pool
+                first = false;
+                wait_close.recv().unwrap();
+                drop(stream);
+            } else {
+                streams.push(stream);
+            }
+        }
+    });
+
+    let pool = ConsolePool::new(builder(port), 1);
+    drop(pool.get().unwrap());
+    wait_accepted.recv_timeout(Duration::from_secs(5)).unwrap();
+    close.send(()).unwrap();
+    // give the FIN some time to arrive
+    thread::sleep(Duration::from_millis(100));
+
+    let console = pool.get().unwrap();
+    assert!(console.is_usable());
+    assert_eq!(pool.open_connections(), 1);
+    // a new connection has been opened instead of handing out the closed one
+    wait_accepted.recv_timeout(Duration::from_secs(5)).unwrap();
+}
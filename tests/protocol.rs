@@ -0,0 +1,151 @@
+use std::io::{Cursor, Read, Write};
+
+use lib_rs_dnsdist_console::{
+    decode_frame_length, derive_session_nonces, ConsoleProtocol, DNSDistConsoleError,
+    FRAME_LENGTH_SIZE,
+};
+use sodiumoxide::crypto::secretbox;
+
+const KEY: [u8; secretbox::KEYBYTES] = [1; secretbox::KEYBYTES];
+
+/// A stream reading from a fixed buffer and recording what is written to it
+struct MemoryStream {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns the client side of a session and the matching server side
+fn session() -> (ConsoleProtocol, ConsoleProtocol) {
+    let (reading_nonce, writing_nonce) = derive_session_nonces(&[2; 24], &[3; 24]);
+    (
+        ConsoleProtocol::new(KEY, reading_nonce, writing_nonce),
+        ConsoleProtocol::new(KEY, writing_nonce, reading_nonce),
+    )
+}
+
+#[test]
+fn handshake_in_memory() {
+    sodiumoxide::init().unwrap();
+
+    let server_nonce = [7_u8; secretbox::NONCEBYTES];
+    let mut stream = MemoryStream {
+        input: Cursor::new(server_nonce.to_vec()),
+        output: Vec::new(),
+    };
+    let protocol = ConsoleProtocol::handshake(&mut stream, KEY).unwrap();
+    assert_eq!(stream.output.len(), secretbox::NONCEBYTES);
+
+    let mut client_nonce = [0_u8; secretbox::NONCEBYTES];
+    client_nonce.copy_from_slice(&stream.output);
+    let (reading_nonce, writing_nonce) = derive_session_nonces(&client_nonce, &server_nonce);
+    assert_eq!(protocol.reading_nonce(), reading_nonce);
+    assert_eq!(protocol.writing_nonce(), writing_nonce);
+}
+
+#[test]
+fn handshake_truncated_nonce() {
+    let mut stream = MemoryStream {
+        input: Cursor::new(vec![7; 10]),
+        output: Vec::new(),
+    };
+    match ConsoleProtocol::handshake(&mut stream, KEY) {
+        Err(DNSDistConsoleError::HandshakeFailed(message)) => {
+            assert!(message.contains("only 10 of the 24 bytes"), "{}", message)
+        }
+        _ => panic!("expected a handshake failure"),
+    }
+
+    let mut stream = MemoryStream {
+        input: Cursor::new(Vec::new()),
+        output: Vec::new(),
+    };
+    match ConsoleProtocol::handshake(&mut stream, KEY) {
+        Err(DNSDistConsoleError::HandshakeFailed(message)) => {
+            assert!(message.contains("before sending its nonce"), "{}", message)
+        }
+        _ => panic!("expected a handshake failure"),
+    }
+}
+
+#[test]
+fn encode_decode() {
+    let (mut client, mut server) = session();
+    for command in &["showVersion()", "showServers()", ""] {
+        let frame = client.encode_command(command).unwrap();
+        assert_eq!(
+            u32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]) as usize,
+            command.len() + secretbox::MACBYTES
+        );
+        assert_eq!(server.decode_response(&frame[4..]).unwrap(), *command);
+    }
+    assert_eq!(client.writing_nonce(), server.reading_nonce());
+}
+
+#[test]
+fn decode_replayed_frame() {
+    let (mut client, mut server) = session();
+    let frame = client.encode_command("showVersion()").unwrap();
+    assert!(server.decode_response(&frame[4..]).is_ok());
+    // the nonce has moved on, so the same frame cannot be decrypted twice
+    match server.decode_response(&frame[4..]) {
+        Err(DNSDistConsoleError::DecryptionError) => {}
+        _ => panic!("expected a decryption error"),
+    }
+}
+
+#[test]
+fn length_in_network_byte_order() {
+    assert_eq!(FRAME_LENGTH_SIZE, 4);
+    let (mut client, _) = session();
+    // 300 bytes of command and the 16 bytes of the MAC: 316, 0x013c
+    let frame = client.encode_command(&"a".repeat(300)).unwrap();
+    assert_eq!(frame[..FRAME_LENGTH_SIZE], [0x00, 0x00, 0x01, 0x3c]);
+    assert_eq!(frame.len(), FRAME_LENGTH_SIZE + 0x013c);
+}
+
+#[test]
+fn handshake_without_stream() {
+    sodiumoxide::init().unwrap();
+
+    let client_nonce = ConsoleProtocol::client_nonce();
+    assert_ne!(client_nonce, ConsoleProtocol::client_nonce());
+    let server_nonce = [7_u8; secretbox::NONCEBYTES];
+    let mut client = ConsoleProtocol::from_handshake(KEY, &client_nonce, &server_nonce).unwrap();
+    let (reading_nonce, writing_nonce) = derive_session_nonces(&client_nonce, &server_nonce);
+    assert_eq!(client.reading_nonce(), reading_nonce);
+    assert_eq!(client.writing_nonce(), writing_nonce);
+
+    let mut server = ConsoleProtocol::new(KEY, writing_nonce, reading_nonce);
+    let frame = client.encode_command("showVersion()").unwrap();
+    let mut prefix = [0_u8; FRAME_LENGTH_SIZE];
+    prefix.copy_from_slice(&frame[..FRAME_LENGTH_SIZE]);
+    assert_eq!(
+        decode_frame_length(prefix) as usize,
+        frame.len() - FRAME_LENGTH_SIZE
+    );
+    assert_eq!(
+        server.decode_response(&frame[FRAME_LENGTH_SIZE..]).unwrap(),
+        "showVersion()"
+    );
+
+    assert!(matches!(
+        ConsoleProtocol::from_handshake([0_u8; secretbox::KEYBYTES], &client_nonce, &server_nonce),
+        Err(DNSDistConsoleError::InvalidKey(_))
+    ));
+}
@@ -0,0 +1,102 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+
+use lib_rs_dnsdist_console::{DNSDistConsoleBuilder, DNSDistConsoleError, ProxyHeader};
+use sodiumoxide::crypto::secretbox;
+
+const KEY: [u8; secretbox::KEYBYTES] = [1; secretbox::KEYBYTES];
+
+/// Connects with `header` and returns what the server received before the client nonce
+fn received_header(header: ProxyHeader, size: usize) -> Vec<u8> {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut header = vec![0_u8; size];
+        stream.read_exact(&mut header).unwrap();
+        let mut client_nonce = [0_u8; secretbox::NONCEBYTES];
+        stream.read_exact(&mut client_nonce).unwrap();
+        stream.write_all(&[0_u8; secretbox::NONCEBYTES]).unwrap();
+        header
+    });
+
+    let console = DNSDistConsoleBuilder::new()
+        .host("127.0.0.1".to_string())
+        .port(port)
+        .key(KEY)
+        .proxy_header(header)
+        .build()
+        .unwrap();
+    assert_eq!(console.bytes_sent(), (size + secretbox::NONCEBYTES) as u64);
+    server.join().unwrap()
+}
+
+#[test]
+fn proxy_v1() {
+    sodiumoxide::init().unwrap();
+
+    let expected = b"PROXY TCP4 192.0.2.1 198.51.100.1 53000 5199\r\n";
+    let header = ProxyHeader::V1 {
+        source: "192.0.2.1:53000".parse().unwrap(),
+        destination: "198.51.100.1:5199".parse().unwrap(),
+    };
+    assert_eq!(received_header(header, expected.len()), expected);
+
+    let header = ProxyHeader::V1 {
+        source: "[2001:db8::1]:53000".parse().unwrap(),
+        destination: "[2001:db8::2]:5199".parse().unwrap(),
+    };
+    assert_eq!(
+        header.to_bytes().unwrap(),
+        b"PROXY TCP6 2001:db8::1 2001:db8::2 53000 5199\r\n"
+    );
+}
+
+#[test]
+fn proxy_v2() {
+    sodiumoxide::init().unwrap();
+
+    let header = ProxyHeader::V2 {
+        source: "192.0.2.1:53000".parse().unwrap(),
+        destination: "198.51.100.1:5199".parse().unwrap(),
+    };
+    let mut expected = vec![
+        0x0d, 0x0a, 0x0d, 0x0a, 0x00, 0x0d, 0x0a, 0x51, 0x55, 0x49, 0x54, 0x0a, 0x21, 0x11, 0x00,
+        0x0c,
+    ];
+    expected.extend_from_slice(&[192, 0, 2, 1, 198, 51, 100, 1]);
+    expected.extend_from_slice(&53000_u16.to_be_bytes());
+    expected.extend_from_slice(&5199_u16.to_be_bytes());
+    assert_eq!(received_header(header, expected.len()), expected);
+
+    let header = ProxyHeader::V2 {
+        source: "[2001:db8::1]:53000".parse().unwrap(),
+        destination: "[2001:db8::2]:5199".parse().unwrap(),
+    };
+    let bytes = header.to_bytes().unwrap();
+    assert_eq!(bytes.len(), 16 + 36);
+    assert_eq!(bytes[13], 0x21);
+    assert_eq!(bytes[14..16], [0x00, 0x24]);
+}
+
+#[test]
+fn proxy_mixed_families() {
+    let source: SocketAddr = "192.0.2.1:53000".parse().unwrap();
+    let destination: SocketAddr = "[2001:db8::2]:5199".parse().unwrap();
+    for header in &[
+        ProxyHeader::V1 {
+            source,
+            destination,
+        },
+        ProxyHeader::V2 {
+            source,
+            destination,
+        },
+    ] {
+        assert!(matches!(
+            header.to_bytes(),
+            Err(DNSDistConsoleError::InvalidArgument(_))
+        ));
+    }
+}
@@ -0,0 +1,96 @@
+use lib_rs_dnsdist_console::{
+    DNSDistConsole, DNSDistConsoleError, MockConsoleServer, ParseError, PoolServersQuery, Query,
+    ServerStatus, ServerStatusQuery,
+};
+
+const KEY: [u8; sodiumoxide::crypto::secretbox::KEYBYTES] =
+    [1; sodiumoxide::crypto::secretbox::KEYBYTES];
+
+#[test]
+fn server_status_query() {
+    let query = ServerStatusQuery::new(3);
+    assert!(query.expression().contains("getServer(3)"));
+    assert_eq!(
+        query.parse("ns1\ntrue\n2\n1\n10\n").unwrap(),
+        ServerStatus {
+            name: "ns1".to_string(),
+            up: true,
+            outstanding: 2,
+            weight: 1,
+            order: 10,
+        }
+    );
+    // servers do not always have a name
+    assert_eq!(query.parse("\nfalse\n0\n1\n1\n").unwrap().name, "");
+    assert!(matches!(
+        query.parse("ns1\ntrue\n2\n"),
+        Err(ParseError::InvalidLine(1, _))
+    ));
+    assert!(matches!(
+        query.parse("ns1\nmaybe\n2\n1\n10\n"),
+        Err(ParseError::InvalidLine(2, _))
+    ));
+    assert!(matches!(
+        query.parse("ns1\ntrue\n2\n1\nfirst\n"),
+        Err(ParseError::InvalidLine(5, _))
+    ));
+}
+
+#[test]
+fn pool_servers_query() {
+    let query = PoolServersQuery::new("abuse");
+    assert!(query.expression().contains("getPoolServers(\"abuse\")"));
+    assert_eq!(
+        query.parse("ns1 (192.0.2.1:53)\n192.0.2.2:53\n").unwrap(),
+        vec!["ns1 (192.0.2.1:53)", "192.0.2.2:53"]
+    );
+    assert!(query.parse("\n").unwrap().is_empty());
+
+    // the name of the pool cannot end the string literal
+    let expression = PoolServersQuery::new("a\") os.exit(\\\n").expression();
+    assert!(expression.contains("getPoolServers(\"a\\\") os.exit(\\\\\\010\")"));
+}
+
+#[test]
+fn query_console() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::scripted(
+        KEY,
+        vec![
+            "ns1\ntrue\n0\n1\n1\n".to_string(),
+            "Error: [string \"chunk\"]:1: attempt to index a nil value (local 's')\n".to_string(),
+        ],
+    )
+    .unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    console.set_read_only(true);
+    assert!(console.query(&ServerStatusQuery::new(0)).unwrap().up);
+    assert!(matches!(
+        console.query(&ServerStatusQuery::new(42)),
+        Err(DNSDistConsoleError::CommandError(_))
+    ));
+    assert_eq!(
+        server.commands(),
+        vec![
+            ServerStatusQuery::new(0).expression(),
+            ServerStatusQuery::new(42).expression()
+        ]
+    );
+}
+
+#[test]
+fn query_max_command_size() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    let query = PoolServersQuery::new("a".repeat(1024));
+    console.set_max_command_size(1024);
+    assert!(matches!(
+        console.query(&query),
+        Err(DNSDistConsoleError::CommandTooLarge(_))
+    ));
+    assert!(server.commands().is_empty());
+    assert!(console.is_usable());
+}
@@ -0,0 +1,48 @@
+use lib_rs_dnsdist_console::{DNSDistConsole, DNSDistConsoleError, MockConsoleServer};
+
+const KEY: [u8; sodiumoxide::crypto::secretbox::KEYBYTES] =
+    [1; sodiumoxide::crypto::secretbox::KEYBYTES];
+
+#[test]
+fn read_only_mode() {
+    sodiumoxide::init().unwrap();
+
+    let server = MockConsoleServer::echo(KEY).unwrap();
+    let mut console = DNSDistConsole::new("127.0.0.1".to_string(), server.port(), KEY).unwrap();
+    console.set_read_only(true);
+
+    let accepted = vec![
+        "showVersion()",
+        " dumpStats() ",
+        "getPool('abuse')",
+        "showServers({showUUIDs=true})",
+        "showResponseLatency(\"a;b:c(d)\", 1.5, -2, nil)",
+    ];
+    for command in &accepted {
+        assert_eq!(console.execute(command).unwrap(), *command);
+    }
+
+    let rejected = vec![
+        "addACL('192.0.2.0/24')",
+        "setACL({})",
+        "showVersion",
+        "showVersion(); addACL('0.0.0.0/0')",
+        "showVersion()\naddACL('0.0.0.0/0')",
+        "getServer(0):setDown()",
+        "showServers(addACL('0.0.0.0/0'))",
+        "showServers(addACL'0.0.0.0/0')",
+        "showServers(newServer{address='192.0.2.1'})",
+        "showVersion('unterminated)",
+    ];
+    for command in &rejected {
+        match console.execute(command) {
+            Err(DNSDistConsoleError::CommandRejected(rejected)) => assert_eq!(rejected, *command),
+            _ => panic!("expected {} to be rejected", command),
+        }
+    }
+    assert!(console.is_usable());
+    assert_eq!(server.commands(), accepted);
+
+    console.set_read_only(false);
+    assert_eq!(console.execute("setACL({})").unwrap(), "setACL({})");
+}
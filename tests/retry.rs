@@ -0,0 +1,184 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lib_rs_dnsdist_console::{
+    derive_session_nonces, execute_before, execute_command_with_retry, ConsoleProtocol,
+    DNSDistConsoleError, MockConsoleServer, Phase,
+};
+use sodiumoxide::crypto::secretbox;
+
+const KEY: [u8; secretbox::KEYBYTES] = [1; secretbox::KEYBYTES];
+
+/// Performs the server side of the handshake, then reads one command
+fn accept_command(stream: &mut TcpStream, key: [u8; secretbox::KEYBYTES]) -> ConsoleProtocol {
+    let mut client_nonce = [0_u8; secretbox::NONCEBYTES];
+    stream.read_exact(&mut client_nonce).unwrap();
+    let server_nonce = [7_u8; secretbox::NONCEBYTES];
+    stream.write_all(&server_nonce).unwrap();
+    let (reading_nonce, writing_nonce) = derive_session_nonces(&client_nonce, &server_nonce);
+    // the server uses the nonces the other way around
+    let mut protocol = ConsoleProtocol::new(key, writing_nonce, reading_nonce);
+
+    let mut len_buffer = [0_u8; 4];
+    stream.read_exact(&mut len_buffer).unwrap();
+    let mut frame = vec![0_u8; u32::from_be_bytes(len_buffer) as usize];
+    stream.read_exact(&mut frame).unwrap();
+    if key == KEY {
+        assert_eq!(protocol.decode_response(&frame).unwrap(), "showVersion()");
+    }
+    protocol
+}
+
+#[test]
+fn retry_after_reset() {
+    sodiumoxide::init().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        // close the first connection in the middle of the response
+        let (mut stream, _) = listener.accept().unwrap();
+        accept_command(&mut stream, KEY);
+        stream.write_all(&64_u32.to_be_bytes()).unwrap();
+        drop(stream);
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut protocol = accept_command(&mut stream, KEY);
+        let frame = protocol.encode_command("dnsdist 1.6.1").unwrap();
+        stream.write_all(&frame).unwrap();
+    });
+
+    let response = execute_command_with_retry(
+        "127.0.0.1".to_string(),
+        port,
+        KEY,
+        "showVersion()",
+        3,
+        Duration::from_millis(10),
+    )
+    .unwrap();
+    assert_eq!(response, "dnsdist 1.6.1");
+    server.join().unwrap();
+}
+
+#[test]
+fn no_retry_on_key_mismatch() {
+    sodiumoxide::init().unwrap();
+
+    // like dnsdist, the server closes the connection when it cannot decrypt the command
+    let server = MockConsoleServer::echo([2; secretbox::KEYBYTES]).unwrap();
+    match execute_command_with_retry(
+        "127.0.0.1".to_string(),
+        server.port(),
+        KEY,
+        "showVersion()",
+        3,
+        Duration::from_millis(10),
+    ) {
+        Err(DNSDistConsoleError::ClosedBeforeKeyConfirmed) => {}
+        _ => panic!("expected a closed before key confirmed error"),
+    }
+    assert_eq!(server.connections(), 1);
+
+    match execute_before(
+        "127.0.0.1".to_string(),
+        server.port(),
+        KEY,
+        "showVersion()",
+        Instant::now() + Duration::from_secs(5),
+    ) {
+        Err(DNSDistConsoleError::ClosedBeforeKeyConfirmed) => {}
+        _ => panic!("expected a closed before key confirmed error"),
+    }
+    assert_eq!(server.connections(), 2);
+}
+
+#[test]
+fn retries_exhausted() {
+    // find a port nobody is listening on
+    let port = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+
+    let start = Instant::now();
+    match execute_command_with_retry(
+        "127.0.0.1".to_string(),
+        port,
+        KEY,
+        "showVersion()",
+        2,
+        Duration::from_millis(50),
+    ) {
+        Err(DNSDistConsoleError::TransportError(Phase::Connect, _)) => {}
+        _ => panic!("expected a connection error"),
+    }
+    // 50ms before the first retry, 100ms before the second one
+    assert!(start.elapsed() >= Duration::from_millis(150));
+}
+
+#[test]
+fn deadline_after_reset() {
+    sodiumoxide::init().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        // close the first two connections in the middle of the response
+        for _ in 0..2 {
+            let (mut stream, _) = listener.accept().unwrap();
+            accept_command(&mut stream, KEY);
+            stream.write_all(&64_u32.to_be_bytes()).unwrap();
+        }
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut protocol = accept_command(&mut stream, KEY);
+        let frame = protocol.encode_command("dnsdist 1.6.1").unwrap();
+        stream.write_all(&frame).unwrap();
+    });
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let response = execute_before(
+        "127.0.0.1".to_string(),
+        port,
+        KEY,
+        "showVersion()",
+        deadline,
+    )
+    .unwrap();
+    assert_eq!(response, "dnsdist 1.6.1");
+    server.join().unwrap();
+}
+
+#[test]
+fn deadline_exceeded() {
+    // find a port nobody is listening on
+    let port = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().port()
+    };
+
+    let start = Instant::now();
+    let deadline = start + Duration::from_millis(500);
+    match execute_before(
+        "127.0.0.1".to_string(),
+        port,
+        KEY,
+        "showVersion()",
+        deadline,
+    ) {
+        Err(DNSDistConsoleError::Timeout) => {}
+        _ => panic!("expected a timeout"),
+    }
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(500));
+    assert!(elapsed < Duration::from_secs(2));
+
+    // a deadline that has already passed
+    assert!(matches!(
+        execute_before("127.0.0.1".to_string(), port, KEY, "showVersion()", start),
+        Err(DNSDistConsoleError::Timeout)
+    ));
+}
@@ -0,0 +1,38 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+use lib_rs_dnsdist_console::{ConsolePool, DNSDistConsole, DNSDistConsoleBuilder};
+
+fn _assert_send<T: Send>() {}
+fn _assert_sync<T: Sync>() {}
+
+#[test]
+fn types_are_send() {
+    _assert_send::<DNSDistConsole>();
+    _assert_send::<DNSDistConsoleBuilder>();
+    _assert_send::<ConsolePool>();
+    _assert_sync::<ConsolePool>();
+}
+
+#[test]
+fn move_console_to_thread() {
+    sodiumoxide::init().unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut client_nonce = [0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES];
+        stream.read_exact(&mut client_nonce).unwrap();
+        stream
+            .write_all(&[0_u8; sodiumoxide::crypto::secretbox::NONCEBYTES])
+            .unwrap();
+    });
+
+    let key = [1_u8; sodiumoxide::crypto::secretbox::KEYBYTES];
+    let console = DNSDistConsole::new("127.0.0.1".to_string(), port, key).unwrap();
+    let worker = thread::spawn(move || console.is_usable());
+    assert!(worker.join().unwrap());
+    server.join().unwrap();
+}